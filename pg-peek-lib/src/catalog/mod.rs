@@ -0,0 +1,1911 @@
+//! System catalogs read straight from the files of a database (`pg_class.h`,
+//! `pg_attribute.h`, `pg_namespace.h`, `pg_type.h`).
+//!
+//! The catalogs describe themselves, so their own rows are decoded with column
+//! layouts hard-coded per server version, as the bootstrap code does. Only the
+//! fixed-width leading columns are read. Rows are filtered by MVCC visibility,
+//! since every catalog update leaves the old row version behind.
+//!
+//! Layouts are those of PostgreSQL 12 onwards; before that `oid` was a hidden
+//! system column rather than an ordinary one.
+
+use crate::control::ControlFileData;
+use crate::datum::{oid, Attribute, Datum, TupleDescriptor};
+use crate::relation::RelationReader;
+use crate::relmap::{RelMapFile, RELMAPPER_FILENAME};
+use crate::types::{TypeAlign, TypeStorage, TypeType};
+use crate::visibility::{
+    tuple_visibility, ClusterOracle, Snapshot, TransactionOracle, TupleVisibility,
+};
+use crate::{Endianness, PageItem, TransactionId};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+
+/// OID of `pg_type`, and the filenode it has until rewritten.
+pub const TYPE_RELATION_ID: u32 = 1247;
+/// OID of `pg_attribute`.
+pub const ATTRIBUTE_RELATION_ID: u32 = 1249;
+/// OID of `pg_class`.
+pub const RELATION_RELATION_ID: u32 = 1259;
+/// OID of `pg_namespace`.
+pub const NAMESPACE_RELATION_ID: u32 = 2615;
+/// OID of `pg_database`, a shared catalog.
+pub const DATABASE_RELATION_ID: u32 = 1262;
+
+/// OID of the `pg_default` tablespace, `base` (`DEFAULTTABLESPACE_OID`).
+pub const DEFAULT_TABLESPACE_OID: u32 = 1663;
+/// OID of the `pg_global` tablespace, `global` (`GLOBALTABLESPACE_OID`).
+pub const GLOBAL_TABLESPACE_OID: u32 = 1664;
+
+/// Oldest major version whose catalog layout is understood.
+pub const MIN_CATALOG_VERSION: u32 = 12;
+
+/// Default `NAMEDATALEN`.
+pub(crate) const DEFAULT_NAME_DATA_LEN: i16 = 64;
+
+/// Kind of relation (`relkind`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum RelKind {
+    #[strum(serialize = "r")]
+    Table,
+    #[strum(serialize = "i")]
+    Index,
+    #[strum(serialize = "S")]
+    Sequence,
+    #[strum(serialize = "t")]
+    ToastTable,
+    #[strum(serialize = "v")]
+    View,
+    #[strum(serialize = "m")]
+    MaterializedView,
+    #[strum(serialize = "c")]
+    CompositeType,
+    #[strum(serialize = "f")]
+    ForeignTable,
+    #[strum(serialize = "p")]
+    PartitionedTable,
+    #[strum(serialize = "I")]
+    PartitionedIndex,
+}
+
+impl RelKind {
+    /// Short name of the kind, such as `table` or `matview`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RelKind::Table => "table",
+            RelKind::Index => "index",
+            RelKind::Sequence => "sequence",
+            RelKind::ToastTable => "toast",
+            RelKind::View => "view",
+            RelKind::MaterializedView => "matview",
+            RelKind::CompositeType => "type",
+            RelKind::ForeignTable => "foreign",
+            RelKind::PartitionedTable => "partitioned",
+            RelKind::PartitionedIndex => "partitioned index",
+        }
+    }
+
+    /// Whether relations of this kind have storage of their own.
+    pub fn has_storage(&self) -> bool {
+        matches!(
+            self,
+            RelKind::Table
+                | RelKind::Index
+                | RelKind::Sequence
+                | RelKind::ToastTable
+                | RelKind::MaterializedView
+        )
+    }
+}
+
+/// How a relation is persisted (`relpersistence`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum RelPersistence {
+    #[strum(serialize = "p")]
+    Permanent,
+    #[strum(serialize = "u")]
+    Unlogged,
+    #[strum(serialize = "t")]
+    Temp,
+}
+
+/// A row of `pg_database`.
+#[derive(Debug, Clone)]
+pub struct DatabaseRow {
+    oid: u32,
+    datname: String,
+    datistemplate: bool,
+    /// False for `template0`, or a database half-dropped (`datconnlimit` -2).
+    datallowconn: bool,
+    datfrozenxid: TransactionId,
+    datminmxid: u32,
+    /// Default tablespace, holding the database's catalogs.
+    dattablespace: u32,
+}
+
+impl DatabaseRow {
+    pub fn oid(&self) -> u32 {
+        self.oid
+    }
+
+    pub fn datname(&self) -> &str {
+        &self.datname
+    }
+
+    pub fn datistemplate(&self) -> bool {
+        self.datistemplate
+    }
+
+    pub fn datallowconn(&self) -> bool {
+        self.datallowconn
+    }
+
+    pub fn datfrozenxid(&self) -> TransactionId {
+        self.datfrozenxid
+    }
+
+    pub fn datminmxid(&self) -> u32 {
+        self.datminmxid
+    }
+
+    pub fn dattablespace(&self) -> u32 {
+        self.dattablespace
+    }
+}
+
+/// A row of `pg_namespace`.
+#[derive(Debug, Clone)]
+pub struct NamespaceRow {
+    oid: u32,
+    nspname: String,
+    nspowner: u32,
+}
+
+impl NamespaceRow {
+    pub fn oid(&self) -> u32 {
+        self.oid
+    }
+
+    pub fn nspname(&self) -> &str {
+        &self.nspname
+    }
+
+    pub fn nspowner(&self) -> u32 {
+        self.nspowner
+    }
+}
+
+/// A row of `pg_class`.
+#[derive(Debug, Clone)]
+pub struct ClassRow {
+    oid: u32,
+    relname: String,
+    relnamespace: u32,
+    reltype: u32,
+    relowner: u32,
+    relam: u32,
+    /// Zero for mapped catalogs, whose filenode is in `pg_filenode.map`.
+    relfilenode: u32,
+    /// Zero for the database's default tablespace.
+    reltablespace: u32,
+    relpages: i32,
+    reltuples: f32,
+    reltoastrelid: u32,
+    relisshared: bool,
+    relpersistence: RelPersistence,
+    relkind: RelKind,
+    relnatts: i16,
+}
+
+impl ClassRow {
+    pub fn oid(&self) -> u32 {
+        self.oid
+    }
+
+    pub fn relname(&self) -> &str {
+        &self.relname
+    }
+
+    pub fn relnamespace(&self) -> u32 {
+        self.relnamespace
+    }
+
+    pub fn reltype(&self) -> u32 {
+        self.reltype
+    }
+
+    pub fn relowner(&self) -> u32 {
+        self.relowner
+    }
+
+    pub fn relam(&self) -> u32 {
+        self.relam
+    }
+
+    pub fn relfilenode(&self) -> u32 {
+        self.relfilenode
+    }
+
+    pub fn reltablespace(&self) -> u32 {
+        self.reltablespace
+    }
+
+    pub fn relpages(&self) -> i32 {
+        self.relpages
+    }
+
+    pub fn reltuples(&self) -> f32 {
+        self.reltuples
+    }
+
+    pub fn reltoastrelid(&self) -> u32 {
+        self.reltoastrelid
+    }
+
+    pub fn relisshared(&self) -> bool {
+        self.relisshared
+    }
+
+    pub fn relpersistence(&self) -> RelPersistence {
+        self.relpersistence
+    }
+
+    pub fn relkind(&self) -> RelKind {
+        self.relkind
+    }
+
+    pub fn relnatts(&self) -> i16 {
+        self.relnatts
+    }
+}
+
+/// A row of `pg_attribute`.
+#[derive(Debug, Clone)]
+pub struct AttributeRow {
+    attrelid: u32,
+    attname: String,
+    /// Zero once the column is dropped.
+    atttypid: u32,
+    attlen: i16,
+    /// Negative for system columns.
+    attnum: i16,
+    attndims: i32,
+    /// Type-specific modifier, such as a `varchar` length, or -1.
+    atttypmod: i32,
+    attbyval: bool,
+    attalign: TypeAlign,
+    attstorage: TypeStorage,
+    attnotnull: bool,
+    attisdropped: bool,
+    attcollation: u32,
+}
+
+impl AttributeRow {
+    pub fn attrelid(&self) -> u32 {
+        self.attrelid
+    }
+
+    pub fn attname(&self) -> &str {
+        &self.attname
+    }
+
+    pub fn atttypid(&self) -> u32 {
+        self.atttypid
+    }
+
+    pub fn attlen(&self) -> i16 {
+        self.attlen
+    }
+
+    pub fn attnum(&self) -> i16 {
+        self.attnum
+    }
+
+    pub fn attndims(&self) -> i32 {
+        self.attndims
+    }
+
+    pub fn atttypmod(&self) -> i32 {
+        self.atttypmod
+    }
+
+    pub fn attbyval(&self) -> bool {
+        self.attbyval
+    }
+
+    pub fn attalign(&self) -> TypeAlign {
+        self.attalign
+    }
+
+    pub fn attstorage(&self) -> TypeStorage {
+        self.attstorage
+    }
+
+    pub fn attnotnull(&self) -> bool {
+        self.attnotnull
+    }
+
+    pub fn attisdropped(&self) -> bool {
+        self.attisdropped
+    }
+
+    pub fn attcollation(&self) -> u32 {
+        self.attcollation
+    }
+
+    /// How the column is stored; a dropped column keeps its length and alignment.
+    pub fn to_attribute(&self) -> Attribute {
+        Attribute::new(self.atttypid, self.attlen, self.attbyval, self.attalign)
+    }
+}
+
+/// A row of the database's own `pg_type`, as opposed to the bundled snapshot in
+/// [`crate::types`], so user-defined types are included.
+#[derive(Debug, Clone)]
+pub struct TypeRow {
+    oid: u32,
+    typname: String,
+    typnamespace: u32,
+    typlen: i16,
+    typbyval: bool,
+    typtype: TypeType,
+    /// The composite type's `pg_class` entry, or zero.
+    typrelid: u32,
+    typelem: u32,
+    typarray: u32,
+    typalign: TypeAlign,
+    typstorage: TypeStorage,
+    /// For a domain, the type it is based on, or zero.
+    typbasetype: u32,
+    typtypmod: i32,
+    typcollation: u32,
+}
+
+impl TypeRow {
+    pub fn oid(&self) -> u32 {
+        self.oid
+    }
+
+    pub fn typname(&self) -> &str {
+        &self.typname
+    }
+
+    pub fn typnamespace(&self) -> u32 {
+        self.typnamespace
+    }
+
+    pub fn typlen(&self) -> i16 {
+        self.typlen
+    }
+
+    pub fn typbyval(&self) -> bool {
+        self.typbyval
+    }
+
+    pub fn typtype(&self) -> TypeType {
+        self.typtype
+    }
+
+    pub fn typrelid(&self) -> u32 {
+        self.typrelid
+    }
+
+    pub fn typelem(&self) -> u32 {
+        self.typelem
+    }
+
+    pub fn typarray(&self) -> u32 {
+        self.typarray
+    }
+
+    pub fn typalign(&self) -> TypeAlign {
+        self.typalign
+    }
+
+    pub fn typstorage(&self) -> TypeStorage {
+        self.typstorage
+    }
+
+    pub fn typbasetype(&self) -> u32 {
+        self.typbasetype
+    }
+
+    pub fn typtypmod(&self) -> i32 {
+        self.typtypmod
+    }
+
+    pub fn typcollation(&self) -> u32 {
+        self.typcollation
+    }
+
+    /// How values of this type are stored.
+    pub fn to_attribute(&self) -> Attribute {
+        Attribute::new(self.oid, self.typlen, self.typbyval, self.typalign)
+    }
+}
+
+/// A relation and its user columns.
+#[derive(Debug, Clone)]
+pub struct Relation {
+    class: ClassRow,
+    /// In `attnum` order, dropped columns included.
+    columns: Vec<AttributeRow>,
+}
+
+impl Relation {
+    pub fn class(&self) -> &ClassRow {
+        &self.class
+    }
+
+    pub fn oid(&self) -> u32 {
+        self.class.oid
+    }
+
+    pub fn name(&self) -> &str {
+        &self.class.relname
+    }
+
+    pub fn columns(&self) -> &[AttributeRow] {
+        &self.columns
+    }
+
+    /// The live column called `name`.
+    pub fn column(&self, name: &str) -> Option<&AttributeRow> {
+        self.columns
+            .iter()
+            .find(|column| !column.attisdropped && column.attname == name)
+    }
+
+    /// Layout of the relation's tuples. Dropped columns are kept, since their
+    /// values still take up space in old rows.
+    pub fn tuple_descriptor(&self) -> TupleDescriptor {
+        TupleDescriptor::new(
+            self.columns
+                .iter()
+                .map(AttributeRow::to_attribute)
+                .collect(),
+        )
+    }
+}
+
+/// A namespace and the relations in it.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    namespace: NamespaceRow,
+    /// Sorted by name.
+    relations: Vec<Relation>,
+}
+
+impl Schema {
+    pub fn namespace(&self) -> &NamespaceRow {
+        &self.namespace
+    }
+
+    pub fn oid(&self) -> u32 {
+        self.namespace.oid
+    }
+
+    pub fn name(&self) -> &str {
+        &self.namespace.nspname
+    }
+
+    pub fn relations(&self) -> &[Relation] {
+        &self.relations
+    }
+
+    pub fn relation(&self, name: &str) -> Option<&Relation> {
+        self.relations
+            .iter()
+            .find(|relation| relation.name() == name)
+    }
+}
+
+/// The schemas, relations, columns and types of one database.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    /// Sorted by name.
+    schemas: Vec<Schema>,
+    types: HashMap<u32, TypeRow>,
+}
+
+impl Catalog {
+    /// Assembles catalog rows into schemas of relations of columns.
+    ///
+    /// System columns are left out, and relations in a namespace that does not
+    /// exist are dropped.
+    pub fn from_rows(
+        namespaces: Vec<NamespaceRow>,
+        classes: Vec<ClassRow>,
+        attributes: Vec<AttributeRow>,
+        types: Vec<TypeRow>,
+    ) -> Catalog {
+        let mut columns: HashMap<u32, Vec<AttributeRow>> = HashMap::new();
+        for attribute in attributes.into_iter().filter(|row| row.attnum > 0) {
+            columns
+                .entry(attribute.attrelid)
+                .or_default()
+                .push(attribute);
+        }
+        let mut relations: HashMap<u32, Vec<Relation>> = HashMap::new();
+        for class in classes {
+            let mut columns = columns.remove(&class.oid).unwrap_or_default();
+            columns.sort_by_key(|column| column.attnum);
+            relations
+                .entry(class.relnamespace)
+                .or_default()
+                .push(Relation { class, columns });
+        }
+
+        let mut schemas: Vec<Schema> = namespaces
+            .into_iter()
+            .map(|namespace| {
+                let mut relations = relations.remove(&namespace.oid).unwrap_or_default();
+                relations.sort_by(|a, b| a.name().cmp(b.name()));
+                Schema {
+                    namespace,
+                    relations,
+                }
+            })
+            .collect();
+        schemas.sort_by(|a, b| a.name().cmp(b.name()));
+
+        Catalog {
+            schemas,
+            types: types.into_iter().map(|row| (row.oid, row)).collect(),
+        }
+    }
+
+    pub fn schemas(&self) -> &[Schema] {
+        &self.schemas
+    }
+
+    pub fn schema(&self, name: &str) -> Option<&Schema> {
+        self.schemas.iter().find(|schema| schema.name() == name)
+    }
+
+    /// The relation `name` in schema `schema`.
+    pub fn relation(&self, schema: &str, name: &str) -> Option<&Relation> {
+        self.schema(schema)?.relation(name)
+    }
+
+    pub fn relation_by_oid(&self, oid: u32) -> Option<&Relation> {
+        self.schemas
+            .iter()
+            .flat_map(|schema| &schema.relations)
+            .find(|relation| relation.oid() == oid)
+    }
+
+    pub fn relations(&self) -> impl Iterator<Item = &Relation> {
+        self.schemas.iter().flat_map(|schema| &schema.relations)
+    }
+
+    pub fn type_by_oid(&self, oid: u32) -> Option<&TypeRow> {
+        self.types.get(&oid)
+    }
+
+    /// The type a value of `type_oid` is stored as: the base type for a domain.
+    pub fn storage_type(&self, type_oid: u32) -> u32 {
+        let mut current = type_oid;
+        // Domains over domains are allowed; the limit guards against a cycle.
+        for _ in 0..self.types.len() {
+            match self.types.get(&current) {
+                Some(row) if row.typtype == TypeType::Domain && row.typbasetype != 0 => {
+                    current = row.typbasetype
+                }
+                _ => break,
+            }
+        }
+        current
+    }
+
+    /// Like [`Relation::tuple_descriptor`], with domains replaced by their base
+    /// types so values of a domain decode like those of its base type.
+    pub fn tuple_descriptor(&self, relation: &Relation) -> TupleDescriptor {
+        TupleDescriptor::new(
+            relation
+                .columns
+                .iter()
+                .map(|column| {
+                    Attribute::new(
+                        self.storage_type(column.atttypid),
+                        column.attlen,
+                        column.attbyval,
+                        column.attalign,
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Major server version recorded in `PG_VERSION` in `directory`, such as 15.
+pub fn read_major_version(directory: &Path) -> io::Result<u32> {
+    let path = directory.join("PG_VERSION");
+    let version = std::fs::read_to_string(&path)?;
+    version
+        .trim()
+        .split('.')
+        .next()
+        .and_then(|major| major.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} has unrecognised version {:?}",
+                    path.display(),
+                    version.trim()
+                ),
+            )
+        })
+}
+
+/// Name of the directory a tablespace keeps per server version, such as
+/// `PG_15_202209061` (`TABLESPACE_VERSION_DIRECTORY`).
+pub fn tablespace_version_directory(major_version: u32, catalog_version_no: u32) -> String {
+    format!("PG_{}_{}", major_version, catalog_version_no)
+}
+
+/// Directory holding the files of database `database_oid` in tablespace `spcoid`
+/// (`GetDatabasePath`).
+///
+/// Other tablespaces live under `pg_tblspc/<spcoid>`, normally a symlink to
+/// the tablespace's location.
+pub fn database_path(
+    data_directory: &Path,
+    spcoid: u32,
+    database_oid: u32,
+    version_directory: &str,
+) -> PathBuf {
+    match spcoid {
+        GLOBAL_TABLESPACE_OID => data_directory.join("global"),
+        DEFAULT_TABLESPACE_OID => data_directory.join("base").join(database_oid.to_string()),
+        spcoid => data_directory
+            .join("pg_tblspc")
+            .join(spcoid.to_string())
+            .join(version_directory)
+            .join(database_oid.to_string()),
+    }
+}
+
+/// Physical identity of a relation's storage (`RelFileLocator`): its tablespace,
+/// its database, zero for shared relations, and its filenode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RelFileLocator {
+    spc_oid: u32,
+    db_oid: u32,
+    rel_number: u32,
+}
+
+impl RelFileLocator {
+    pub fn new(spc_oid: u32, db_oid: u32, rel_number: u32) -> RelFileLocator {
+        RelFileLocator {
+            spc_oid,
+            db_oid,
+            rel_number,
+        }
+    }
+
+    pub fn spc_oid(&self) -> u32 {
+        self.spc_oid
+    }
+
+    pub fn db_oid(&self) -> u32 {
+        self.db_oid
+    }
+
+    pub fn rel_number(&self) -> u32 {
+        self.rel_number
+    }
+}
+
+/// Where `class` in `database` is stored.
+///
+/// Mapped relations, whose `relfilenode` is zero, are looked up in `relmap`,
+/// which must be the map in `global` for shared relations and the database's
+/// own otherwise.
+pub fn relation_locator(
+    database: &DatabaseRow,
+    class: &ClassRow,
+    relmap: &RelMapFile,
+) -> io::Result<RelFileLocator> {
+    if !class.relkind.has_storage() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} has no storage (relkind {})",
+                class.relname, class.relkind
+            ),
+        ));
+    }
+    let rel_number = match class.relfilenode {
+        0 => relmap.filenode(class.oid).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is missing from the relation map", class.relname),
+            )
+        })?,
+        relfilenode => relfilenode,
+    };
+    let (spc_oid, db_oid) = match (class.relisshared, class.reltablespace) {
+        (true, _) => (GLOBAL_TABLESPACE_OID, 0),
+        (false, 0) => (database.dattablespace, database.oid),
+        (false, reltablespace) => (reltablespace, database.oid),
+    };
+    Ok(RelFileLocator::new(spc_oid, db_oid, rel_number))
+}
+
+/// Path of the first segment of the main fork of `class` in `database` (`relpath`).
+///
+/// See [`relation_locator`] for `relmap`. Temporary relations cannot be
+/// located, since their file names include the number of the backend owning
+/// them.
+pub fn relation_path(
+    data_directory: &Path,
+    database: &DatabaseRow,
+    class: &ClassRow,
+    version_directory: &str,
+    relmap: &RelMapFile,
+) -> io::Result<PathBuf> {
+    let locator = relation_locator(database, class, relmap)?;
+    if class.relpersistence == RelPersistence::Temp {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} is a temporary relation", class.relname),
+        ));
+    }
+    Ok(database_path(
+        data_directory,
+        locator.spc_oid,
+        locator.db_oid,
+        version_directory,
+    )
+    .join(locator.rel_number.to_string()))
+}
+
+/// Column names alongside the descriptor decoding them.
+struct CatalogDescriptor {
+    names: Vec<&'static str>,
+    descriptor: TupleDescriptor,
+}
+
+impl CatalogDescriptor {
+    fn new(columns: Vec<(&'static str, Attribute)>) -> CatalogDescriptor {
+        let (names, attributes) = columns.into_iter().unzip();
+        CatalogDescriptor {
+            names,
+            descriptor: TupleDescriptor::new(attributes),
+        }
+    }
+}
+
+fn oid_column(name: &'static str) -> (&'static str, Attribute) {
+    (name, Attribute::new(oid::OIDOID, 4, true, TypeAlign::Int))
+}
+
+fn xid_column(name: &'static str) -> (&'static str, Attribute) {
+    (name, Attribute::new(oid::XIDOID, 4, true, TypeAlign::Int))
+}
+
+fn regproc_column(name: &'static str) -> (&'static str, Attribute) {
+    (
+        name,
+        Attribute::new(oid::REGPROCOID, 4, true, TypeAlign::Int),
+    )
+}
+
+fn int2_column(name: &'static str) -> (&'static str, Attribute) {
+    (
+        name,
+        Attribute::new(oid::INT2OID, 2, true, TypeAlign::Short),
+    )
+}
+
+fn int4_column(name: &'static str) -> (&'static str, Attribute) {
+    (name, Attribute::new(oid::INT4OID, 4, true, TypeAlign::Int))
+}
+
+fn float4_column(name: &'static str) -> (&'static str, Attribute) {
+    (
+        name,
+        Attribute::new(oid::FLOAT4OID, 4, true, TypeAlign::Int),
+    )
+}
+
+fn bool_column(name: &'static str) -> (&'static str, Attribute) {
+    (name, Attribute::new(oid::BOOLOID, 1, true, TypeAlign::Char))
+}
+
+fn char_column(name: &'static str) -> (&'static str, Attribute) {
+    (name, Attribute::new(oid::CHAROID, 1, true, TypeAlign::Char))
+}
+
+/// One decoded catalog row, with its values looked up by column name.
+struct CatalogRow<'a> {
+    names: &'a [&'static str],
+    values: Vec<Datum>,
+}
+
+impl CatalogRow<'_> {
+    fn get(&self, name: &str) -> io::Result<&Datum> {
+        self.names
+            .iter()
+            .position(|&column| column == name)
+            .map(|index| &self.values[index])
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("No column {}", name))
+            })
+    }
+
+    fn mismatch(&self, name: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Catalog column {} has unexpected value {:?}",
+                name,
+                self.get(name).ok()
+            ),
+        )
+    }
+
+    fn oid(&self, name: &str) -> io::Result<u32> {
+        match self.get(name)? {
+            Datum::Oid(value) => Ok(*value),
+            _ => Err(self.mismatch(name)),
+        }
+    }
+
+    fn name(&self, name: &str) -> io::Result<String> {
+        match self.get(name)? {
+            Datum::Text(value) => Ok(value.clone()),
+            _ => Err(self.mismatch(name)),
+        }
+    }
+
+    fn int2(&self, name: &str) -> io::Result<i16> {
+        match self.get(name)? {
+            Datum::Int2(value) => Ok(*value),
+            _ => Err(self.mismatch(name)),
+        }
+    }
+
+    fn int4(&self, name: &str) -> io::Result<i32> {
+        match self.get(name)? {
+            Datum::Int4(value) => Ok(*value),
+            // `attndims` narrowed to int2 in PostgreSQL 17.
+            Datum::Int2(value) => Ok(*value as i32),
+            _ => Err(self.mismatch(name)),
+        }
+    }
+
+    fn float4(&self, name: &str) -> io::Result<f32> {
+        match self.get(name)? {
+            Datum::Float4(value) => Ok(*value),
+            _ => Err(self.mismatch(name)),
+        }
+    }
+
+    fn bool(&self, name: &str) -> io::Result<bool> {
+        match self.get(name)? {
+            Datum::Bool(value) => Ok(*value),
+            _ => Err(self.mismatch(name)),
+        }
+    }
+
+    /// A `"char"` column holding one of the codes of enum `T`.
+    fn code<T: FromStr>(&self, name: &str) -> io::Result<T> {
+        match self.get(name)? {
+            Datum::Char(value) => {
+                T::from_str(&(*value as u8 as char).to_string()).map_err(|_| self.mismatch(name))
+            }
+            _ => Err(self.mismatch(name)),
+        }
+    }
+}
+
+/// Reads the system catalogs of one database directory.
+pub struct CatalogReader {
+    database_dir: PathBuf,
+    major_version: u32,
+    block_size: Option<usize>,
+    relseg_size: Option<u32>,
+    name_data_len: i16,
+    endianness: Endianness,
+    snapshot: Snapshot,
+    oracle: Box<dyn TransactionOracle>,
+    filenodes: HashMap<u32, u32>,
+}
+
+impl CatalogReader {
+    /// Reads the catalogs in `database_dir` (e.g. `base/16384`), whose server
+    /// version is taken from its `PG_VERSION` file.
+    ///
+    /// Rows are those `snapshot` sees, with transaction outcomes from `oracle`.
+    /// Catalogs are assumed to be stored under a file named after their OID; use
+    /// [`CatalogReader::set_relmap`] or [`CatalogReader::set_filenode`] for any
+    /// that were rewritten since.
+    pub fn new(
+        database_dir: &Path,
+        block_size: Option<usize>,
+        relseg_size: Option<u32>,
+        endianness: Endianness,
+        snapshot: Snapshot,
+        oracle: Box<dyn TransactionOracle>,
+    ) -> io::Result<CatalogReader> {
+        // `global` has no PG_VERSION of its own; the data directory's applies.
+        let version_dir = match database_dir.join("PG_VERSION").is_file() {
+            false => database_dir.parent().unwrap_or(database_dir),
+            true => database_dir,
+        };
+        let major_version = read_major_version(version_dir)?;
+        if major_version < MIN_CATALOG_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "Catalogs of PostgreSQL {} are not supported (oldest is {})",
+                    major_version, MIN_CATALOG_VERSION
+                ),
+            ));
+        }
+        Ok(CatalogReader {
+            database_dir: database_dir.to_path_buf(),
+            major_version,
+            block_size,
+            relseg_size,
+            name_data_len: DEFAULT_NAME_DATA_LEN,
+            endianness,
+            snapshot,
+            oracle,
+            filenodes: HashMap::new(),
+        })
+    }
+
+    /// Reads the catalogs of `database` in `data_directory`, configured from
+    /// its `pg_control` and `pg_filenode.map`, and seeing every committed row.
+    ///
+    /// A map file failing its CRC check is an error, as it is for the server.
+    pub fn open(
+        data_directory: &Path,
+        database: &DatabaseRow,
+        control: &ControlFileData,
+    ) -> io::Result<CatalogReader> {
+        let version_directory = tablespace_version_directory(
+            read_major_version(data_directory)?,
+            control.catalog_version_no(),
+        );
+        let database_dir = database_path(
+            data_directory,
+            database.dattablespace,
+            database.oid,
+            &version_directory,
+        );
+        CatalogReader::open_directory(data_directory, &database_dir, control)
+    }
+
+    /// Reads the shared catalogs in `global`, such as `pg_database`; see
+    /// [`CatalogReader::open`].
+    pub fn open_shared(
+        data_directory: &Path,
+        control: &ControlFileData,
+    ) -> io::Result<CatalogReader> {
+        CatalogReader::open_directory(data_directory, &data_directory.join("global"), control)
+    }
+
+    fn open_directory(
+        data_directory: &Path,
+        database_dir: &Path,
+        control: &ControlFileData,
+    ) -> io::Result<CatalogReader> {
+        let mut reader = CatalogReader::new(
+            database_dir,
+            Some(control.blcksz() as usize),
+            Some(control.relseg_size()),
+            control.endianness(),
+            Snapshot::latest(control.check_point_copy().next_xid()),
+            Box::new(ClusterOracle::open(data_directory, control)),
+        )?;
+        reader.name_data_len = control.name_data_len() as i16;
+
+        let relmap = RelMapFile::from_path(&reader.database_dir, control.endianness())?;
+        if !relmap.crc_is_valid() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} contains incorrect checksum",
+                    reader.database_dir.join(RELMAPPER_FILENAME).display()
+                ),
+            ));
+        }
+        reader.set_relmap(&relmap);
+        Ok(reader)
+    }
+
+    pub fn database_dir(&self) -> &Path {
+        &self.database_dir
+    }
+
+    pub fn major_version(&self) -> u32 {
+        self.major_version
+    }
+
+    /// Records that the catalog `relid` is stored in `relfilenode`.
+    pub fn set_filenode(&mut self, relid: u32, relfilenode: u32) {
+        self.filenodes.insert(relid, relfilenode);
+    }
+
+    /// Records where every relation in the database's `pg_filenode.map` is stored.
+    pub fn set_relmap(&mut self, relmap: &RelMapFile) {
+        for mapping in relmap.mappings() {
+            self.set_filenode(mapping.mapoid(), mapping.mapfilenumber());
+        }
+    }
+
+    /// Path of the first segment of the catalog `relid`.
+    pub fn relation_path(&self, relid: u32) -> PathBuf {
+        let filenode = self.filenodes.get(&relid).copied().unwrap_or(relid);
+        self.database_dir.join(filenode.to_string())
+    }
+
+    fn name_column(&self, name: &'static str) -> (&'static str, Attribute) {
+        (
+            name,
+            Attribute::new(oid::NAMEOID, self.name_data_len, false, TypeAlign::Char),
+        )
+    }
+
+    /// Decodes every visible row of catalog `relid` with `descriptor`.
+    fn scan<T, F>(&self, relid: u32, descriptor: &CatalogDescriptor, f: F) -> io::Result<Vec<T>>
+    where
+        F: FnMut(&CatalogRow) -> io::Result<T>,
+    {
+        self.scan_matching(relid, descriptor, TupleVisibility::is_visible, f)
+    }
+
+    /// Decodes the rows of catalog `relid` whose visibility satisfies `matches`.
+    fn scan_matching<T, F>(
+        &self,
+        relid: u32,
+        descriptor: &CatalogDescriptor,
+        matches: fn(&TupleVisibility) -> bool,
+        mut f: F,
+    ) -> io::Result<Vec<T>>
+    where
+        F: FnMut(&CatalogRow) -> io::Result<T>,
+    {
+        let path = self.relation_path(relid);
+        let mut relation = RelationReader::open_segments(
+            &path,
+            self.block_size,
+            self.relseg_size,
+            self.endianness,
+        )
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+        let mut rows = Vec::new();
+        for page in relation.pages() {
+            for item in page?.items() {
+                let PageItem::Normal(tuple) = item else {
+                    continue;
+                };
+                let visibility =
+                    tuple_visibility(tuple.header(), &self.snapshot, self.oracle.as_ref())?;
+                if !matches(&visibility) {
+                    continue;
+                }
+                let row = CatalogRow {
+                    names: &descriptor.names,
+                    values: tuple.decode(&descriptor.descriptor, self.endianness)?,
+                };
+                rows.push(f(&row)?);
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Visible rows of `pg_database`, a shared catalog; see [`CatalogReader::open_shared`].
+    pub fn databases(&self) -> io::Result<Vec<DatabaseRow>> {
+        let mut columns = vec![
+            oid_column("oid"),
+            self.name_column("datname"),
+            oid_column("datdba"),
+            int4_column("encoding"),
+        ];
+        // PostgreSQL 15 turned datcollate and datctype into text at the end and
+        // dropped datlastsysoid; 17 added dathasloginevt.
+        if self.major_version >= 15 {
+            columns.push(char_column("datlocprovider"));
+        } else {
+            columns.extend([self.name_column("datcollate"), self.name_column("datctype")]);
+        }
+        columns.extend([bool_column("datistemplate"), bool_column("datallowconn")]);
+        if self.major_version >= 17 {
+            columns.push(bool_column("dathasloginevt"));
+        }
+        columns.push(int4_column("datconnlimit"));
+        if self.major_version < 15 {
+            columns.push(oid_column("datlastsysoid"));
+        }
+        columns.extend([
+            xid_column("datfrozenxid"),
+            xid_column("datminmxid"),
+            oid_column("dattablespace"),
+        ]);
+        let descriptor = CatalogDescriptor::new(columns);
+        self.scan(DATABASE_RELATION_ID, &descriptor, |row| {
+            Ok(DatabaseRow {
+                oid: row.oid("oid")?,
+                datname: row.name("datname")?,
+                datistemplate: row.bool("datistemplate")?,
+                datallowconn: row.bool("datallowconn")?,
+                datfrozenxid: TransactionId(row.oid("datfrozenxid")?),
+                datminmxid: row.oid("datminmxid")?,
+                dattablespace: row.oid("dattablespace")?,
+            })
+        })
+    }
+
+    /// Visible rows of `pg_namespace`.
+    pub fn namespaces(&self) -> io::Result<Vec<NamespaceRow>> {
+        let descriptor = CatalogDescriptor::new(vec![
+            oid_column("oid"),
+            self.name_column("nspname"),
+            oid_column("nspowner"),
+        ]);
+        self.scan(NAMESPACE_RELATION_ID, &descriptor, |row| {
+            Ok(NamespaceRow {
+                oid: row.oid("oid")?,
+                nspname: row.name("nspname")?,
+                nspowner: row.oid("nspowner")?,
+            })
+        })
+    }
+
+    /// Visible rows of `pg_class`.
+    pub fn classes(&self) -> io::Result<Vec<ClassRow>> {
+        self.scan_classes(TupleVisibility::is_visible)
+    }
+
+    /// Rows of `pg_class` inserted by transactions still in progress: those of
+    /// relations being created, or giving a relation the new filenode of a
+    /// TRUNCATE or CLUSTER not yet committed.
+    pub fn uncommitted_classes(&self) -> io::Result<Vec<ClassRow>> {
+        self.scan_classes(|visibility| *visibility == TupleVisibility::InsertInProgress)
+    }
+
+    fn scan_classes(&self, matches: fn(&TupleVisibility) -> bool) -> io::Result<Vec<ClassRow>> {
+        let mut columns = vec![
+            oid_column("oid"),
+            self.name_column("relname"),
+            oid_column("relnamespace"),
+            oid_column("reltype"),
+            oid_column("reloftype"),
+            oid_column("relowner"),
+            oid_column("relam"),
+            oid_column("relfilenode"),
+            oid_column("reltablespace"),
+            int4_column("relpages"),
+            float4_column("reltuples"),
+            int4_column("relallvisible"),
+        ];
+        if self.major_version >= 18 {
+            columns.push(int4_column("relallfrozen"));
+        }
+        columns.extend([
+            oid_column("reltoastrelid"),
+            bool_column("relhasindex"),
+            bool_column("relisshared"),
+            char_column("relpersistence"),
+            char_column("relkind"),
+            int2_column("relnatts"),
+        ]);
+        let descriptor = CatalogDescriptor::new(columns);
+        self.scan_matching(RELATION_RELATION_ID, &descriptor, matches, |row| {
+            Ok(ClassRow {
+                oid: row.oid("oid")?,
+                relname: row.name("relname")?,
+                relnamespace: row.oid("relnamespace")?,
+                reltype: row.oid("reltype")?,
+                relowner: row.oid("relowner")?,
+                relam: row.oid("relam")?,
+                relfilenode: row.oid("relfilenode")?,
+                reltablespace: row.oid("reltablespace")?,
+                relpages: row.int4("relpages")?,
+                reltuples: row.float4("reltuples")?,
+                reltoastrelid: row.oid("reltoastrelid")?,
+                relisshared: row.bool("relisshared")?,
+                relpersistence: row.code("relpersistence")?,
+                relkind: row.code("relkind")?,
+                relnatts: row.int2("relnatts")?,
+            })
+        })
+    }
+
+    /// Visible rows of `pg_attribute`.
+    pub fn attributes(&self) -> io::Result<Vec<AttributeRow>> {
+        let mut columns = vec![
+            oid_column("attrelid"),
+            self.name_column("attname"),
+            oid_column("atttypid"),
+        ];
+        // PostgreSQL 17 moved attstattarget out of the fixed part and narrowed
+        // attndims and attinhcount; 14 swapped attalign and attstorage and added
+        // attcompression.
+        if self.major_version >= 17 {
+            columns.extend([
+                int2_column("attlen"),
+                int2_column("attnum"),
+                int4_column("attcacheoff"),
+                int4_column("atttypmod"),
+                int2_column("attndims"),
+            ]);
+        } else {
+            columns.extend([
+                int4_column("attstattarget"),
+                int2_column("attlen"),
+                int2_column("attnum"),
+                int4_column("attndims"),
+                int4_column("attcacheoff"),
+                int4_column("atttypmod"),
+            ]);
+        }
+        columns.push(bool_column("attbyval"));
+        if self.major_version >= 14 {
+            columns.extend([
+                char_column("attalign"),
+                char_column("attstorage"),
+                char_column("attcompression"),
+            ]);
+        } else {
+            columns.extend([char_column("attstorage"), char_column("attalign")]);
+        }
+        columns.extend([
+            bool_column("attnotnull"),
+            bool_column("atthasdef"),
+            bool_column("atthasmissing"),
+            char_column("attidentity"),
+            char_column("attgenerated"),
+            bool_column("attisdropped"),
+            bool_column("attislocal"),
+        ]);
+        columns.push(if self.major_version >= 17 {
+            int2_column("attinhcount")
+        } else {
+            int4_column("attinhcount")
+        });
+        columns.push(oid_column("attcollation"));
+        let descriptor = CatalogDescriptor::new(columns);
+        self.scan(ATTRIBUTE_RELATION_ID, &descriptor, |row| {
+            Ok(AttributeRow {
+                attrelid: row.oid("attrelid")?,
+                attname: row.name("attname")?,
+                atttypid: row.oid("atttypid")?,
+                attlen: row.int2("attlen")?,
+                attnum: row.int2("attnum")?,
+                attndims: row.int4("attndims")?,
+                atttypmod: row.int4("atttypmod")?,
+                attbyval: row.bool("attbyval")?,
+                attalign: row.code("attalign")?,
+                attstorage: row.code("attstorage")?,
+                attnotnull: row.bool("attnotnull")?,
+                attisdropped: row.bool("attisdropped")?,
+                attcollation: row.oid("attcollation")?,
+            })
+        })
+    }
+
+    /// Visible rows of `pg_type`.
+    pub fn types(&self) -> io::Result<Vec<TypeRow>> {
+        let mut columns = vec![
+            oid_column("oid"),
+            self.name_column("typname"),
+            oid_column("typnamespace"),
+            oid_column("typowner"),
+            int2_column("typlen"),
+            bool_column("typbyval"),
+            char_column("typtype"),
+            char_column("typcategory"),
+            bool_column("typispreferred"),
+            bool_column("typisdefined"),
+            char_column("typdelim"),
+            oid_column("typrelid"),
+        ];
+        if self.major_version >= 14 {
+            columns.push(regproc_column("typsubscript"));
+        }
+        columns.extend([
+            oid_column("typelem"),
+            oid_column("typarray"),
+            regproc_column("typinput"),
+            regproc_column("typoutput"),
+            regproc_column("typreceive"),
+            regproc_column("typsend"),
+            regproc_column("typmodin"),
+            regproc_column("typmodout"),
+            regproc_column("typanalyze"),
+            char_column("typalign"),
+            char_column("typstorage"),
+            bool_column("typnotnull"),
+            oid_column("typbasetype"),
+            int4_column("typtypmod"),
+            int4_column("typndims"),
+            oid_column("typcollation"),
+        ]);
+        let descriptor = CatalogDescriptor::new(columns);
+        self.scan(TYPE_RELATION_ID, &descriptor, |row| {
+            Ok(TypeRow {
+                oid: row.oid("oid")?,
+                typname: row.name("typname")?,
+                typnamespace: row.oid("typnamespace")?,
+                typlen: row.int2("typlen")?,
+                typbyval: row.bool("typbyval")?,
+                typtype: row.code("typtype")?,
+                typrelid: row.oid("typrelid")?,
+                typelem: row.oid("typelem")?,
+                typarray: row.oid("typarray")?,
+                typalign: row.code("typalign")?,
+                typstorage: row.code("typstorage")?,
+                typbasetype: row.oid("typbasetype")?,
+                typtypmod: row.int4("typtypmod")?,
+                typcollation: row.oid("typcollation")?,
+            })
+        })
+    }
+
+    /// Reads the four catalogs and assembles them into a [`Catalog`].
+    ///
+    /// `pg_class` comes first, since it records where the unmapped catalogs,
+    /// such as `pg_namespace`, are stored.
+    pub fn read(&mut self) -> io::Result<Catalog> {
+        let classes = self.classes()?;
+        for class in &classes {
+            let catalog = matches!(
+                class.oid,
+                TYPE_RELATION_ID | ATTRIBUTE_RELATION_ID | NAMESPACE_RELATION_ID
+            );
+            if catalog && class.relfilenode != 0 && !self.filenodes.contains_key(&class.oid) {
+                self.filenodes.insert(class.oid, class.relfilenode);
+            }
+        }
+        Ok(Catalog::from_rows(
+            self.namespaces()?,
+            classes,
+            self.attributes()?,
+            self.types()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fill, heap_tuple, tuple, write_relation, Field};
+    use crate::Infomask;
+
+    fn class(oid: u32, relname: &str, relnamespace: u32) -> ClassRow {
+        ClassRow {
+            oid,
+            relname: relname.to_string(),
+            relnamespace,
+            reltype: 0,
+            relowner: 10,
+            relam: 2,
+            relfilenode: oid,
+            reltablespace: 0,
+            relpages: 0,
+            reltuples: -1.0,
+            reltoastrelid: 0,
+            relisshared: false,
+            relpersistence: RelPersistence::Permanent,
+            relkind: RelKind::Table,
+            relnatts: 2,
+        }
+    }
+
+    fn namespace(oid: u32, nspname: &str) -> NamespaceRow {
+        NamespaceRow {
+            oid,
+            nspname: nspname.to_string(),
+            nspowner: 10,
+        }
+    }
+
+    fn attribute(attnum: i16, attname: &str, atttypid: u32, attlen: i16) -> AttributeRow {
+        AttributeRow {
+            attrelid: 16500,
+            attname: attname.to_string(),
+            atttypid,
+            attlen,
+            attnum,
+            attndims: 0,
+            atttypmod: -1,
+            attbyval: true,
+            attalign: if attlen == 8 {
+                TypeAlign::Double
+            } else {
+                TypeAlign::Int
+            },
+            attstorage: TypeStorage::Plain,
+            attnotnull: false,
+            attisdropped: atttypid == 0,
+            attcollation: 0,
+        }
+    }
+
+    #[test]
+    fn assembles_schemas() {
+        let catalog = Catalog::from_rows(
+            vec![namespace(2200, "public"), namespace(16400, "app")],
+            vec![class(16500, "orders", 2200), class(16510, "items", 16400)],
+            vec![
+                attribute(1, "id", oid::INT4OID, 4),
+                attribute(-1, "ctid", 27, 6),
+                attribute(3, "total", oid::INT4OID, 4),
+                attribute(2, "........pg.dropped.2........", 0, 8),
+            ],
+            Vec::new(),
+        );
+
+        let names: Vec<_> = catalog.schemas().iter().map(Schema::name).collect();
+        assert_eq!(names, ["app", "public"]);
+        let orders = catalog.relation("public", "orders").unwrap();
+        let attnums: Vec<_> = orders.columns().iter().map(AttributeRow::attnum).collect();
+        assert_eq!(attnums, [1, 2, 3]);
+        assert!(orders.column("total").is_some());
+        assert!(orders.column("ctid").is_none());
+
+        // The dropped bigint still shapes the layout.
+        let descriptor = orders.tuple_descriptor();
+        assert_eq!(descriptor.len(), 3);
+        assert_eq!(descriptor.attributes()[1].typalign(), TypeAlign::Double);
+        assert_eq!(catalog.relation_by_oid(16510).unwrap().name(), "items");
+    }
+
+    /// A database directory of `major_version` whose catalogs each hold one
+    /// page of `tuples`, and a reader for it.
+    fn catalog_reader(
+        major_version: u32,
+        catalogs: &[(u32, Vec<Vec<u8>>)],
+    ) -> (tempfile::TempDir, CatalogReader) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("PG_VERSION"),
+            format!("{}\n", major_version),
+        )
+        .unwrap();
+        for (relid, tuples) in catalogs {
+            write_relation(&dir.path().join(relid.to_string()), tuples);
+        }
+        let reader = CatalogReader::new(
+            dir.path(),
+            None,
+            None,
+            Endianness::LittleEndian,
+            Snapshot::latest(TransactionId(1000)),
+            Box::new(|_: TransactionId| Ok(crate::visibility::TransactionStatus::Committed)),
+        )
+        .unwrap();
+        (dir, reader)
+    }
+
+    /// `pg_class`, `pg_attribute` and `pg_type` rows in the layout of `major_version`.
+    fn catalog_rows(major_version: u32) -> Vec<(u32, Vec<Vec<u8>>)> {
+        let mut class = vec![
+            Field::Oid(16500),
+            Field::Name("orders"),
+            Field::Oid(2200),
+            Field::Oid(16502),
+            Field::Oid(0),
+            Field::Oid(10),
+            Field::Oid(2),
+            Field::Oid(16501),
+            Field::Oid(0),
+            Field::Int4(3),
+            Field::Float4(120.0),
+            Field::Int4(2),
+        ];
+        if major_version >= 18 {
+            // relallfrozen
+            class.push(Field::Int4(1));
+        }
+        class.extend([
+            Field::Oid(16503),
+            Field::Bool(true),
+            Field::Bool(false),
+            Field::Char(b'u'),
+            Field::Char(b'r'),
+            Field::Int2(3),
+        ]);
+
+        let mut attribute = vec![
+            Field::Oid(16500),
+            Field::Name("total"),
+            Field::Oid(oid::NUMERICOID),
+        ];
+        if major_version >= 17 {
+            // attlen, attnum, attcacheoff, atttypmod, attndims
+            attribute.extend([
+                Field::Int2(-1),
+                Field::Int2(3),
+                Field::Int4(-1),
+                Field::Int4(655366),
+                Field::Int2(1),
+            ]);
+        } else {
+            // attstattarget, attlen, attnum, attndims, attcacheoff, atttypmod
+            attribute.extend([
+                Field::Int4(-1),
+                Field::Int2(-1),
+                Field::Int2(3),
+                Field::Int4(1),
+                Field::Int4(-1),
+                Field::Int4(655366),
+            ]);
+        }
+        attribute.push(Field::Bool(false));
+        if major_version >= 14 {
+            // attalign, attstorage, attcompression
+            attribute.extend([Field::Char(b'i'), Field::Char(b'm'), Field::Char(b'p')]);
+        } else {
+            attribute.extend([Field::Char(b'm'), Field::Char(b'i')]);
+        }
+        // attnotnull to attislocal
+        attribute.extend([
+            Field::Bool(true),
+            Field::Bool(false),
+            Field::Bool(false),
+            Field::Char(0),
+            Field::Char(0),
+            Field::Bool(false),
+            Field::Bool(true),
+        ]);
+        attribute.push(match major_version >= 17 {
+            true => Field::Int2(0),
+            false => Field::Int4(0),
+        });
+        attribute.push(Field::Oid(100));
+
+        let mut pg_type = vec![
+            Field::Oid(16502),
+            Field::Name("_orders"),
+            Field::Oid(2200),
+            Field::Oid(10),
+            Field::Int2(-1),
+            Field::Bool(false),
+            Field::Char(b'b'),
+            Field::Char(b'A'),
+            Field::Bool(false),
+            Field::Bool(true),
+            Field::Char(b','),
+            Field::Oid(0),
+        ];
+        if major_version >= 14 {
+            // typsubscript: array_subscript_handler
+            pg_type.push(Field::Oid(6179));
+        }
+        pg_type.extend([Field::Oid(16504), Field::Oid(0)]);
+        // typinput to typanalyze
+        pg_type.extend([750, 751, 2400, 2401, 0, 0, 3816].map(Field::Oid));
+        pg_type.extend([
+            Field::Char(b'd'),
+            Field::Char(b'x'),
+            Field::Bool(false),
+            Field::Oid(0),
+            Field::Int4(-1),
+            Field::Int4(0),
+            Field::Oid(0),
+        ]);
+
+        vec![
+            (RELATION_RELATION_ID, vec![tuple(&class)]),
+            (ATTRIBUTE_RELATION_ID, vec![tuple(&attribute)]),
+            (TYPE_RELATION_ID, vec![tuple(&pg_type)]),
+        ]
+    }
+
+    #[test]
+    fn decodes_each_catalog_layout() {
+        for major_version in [13, 14, 17, 18] {
+            let (_dir, reader) = catalog_reader(major_version, &catalog_rows(major_version));
+
+            let classes = reader.classes().unwrap();
+            let class = &classes[0];
+            assert_eq!(class.relname(), "orders", "{}", major_version);
+            assert_eq!(class.relfilenode(), 16501);
+            assert_eq!(class.relpages(), 3);
+            assert_eq!(class.reltuples(), 120.0);
+            assert_eq!(class.reltoastrelid(), 16503, "{}", major_version);
+            assert_eq!(class.relpersistence(), RelPersistence::Unlogged);
+            assert_eq!(class.relkind(), RelKind::Table);
+            assert_eq!(class.relnatts(), 3);
+
+            let attributes = reader.attributes().unwrap();
+            let attribute = &attributes[0];
+            assert_eq!(attribute.attname(), "total", "{}", major_version);
+            assert_eq!(attribute.atttypid(), oid::NUMERICOID);
+            assert_eq!(attribute.attlen(), -1);
+            assert_eq!(attribute.attnum(), 3);
+            assert_eq!(attribute.attndims(), 1, "{}", major_version);
+            assert_eq!(attribute.atttypmod(), 655366, "{}", major_version);
+            assert_eq!(attribute.attalign(), TypeAlign::Int, "{}", major_version);
+            assert_eq!(attribute.attstorage(), TypeStorage::Main);
+            assert!(attribute.attnotnull());
+            assert!(!attribute.attisdropped());
+            assert_eq!(attribute.attcollation(), 100, "{}", major_version);
+
+            let types = reader.types().unwrap();
+            let pg_type = &types[0];
+            assert_eq!(pg_type.typname(), "_orders", "{}", major_version);
+            assert_eq!(pg_type.typtype(), TypeType::Base);
+            assert_eq!(pg_type.typelem(), 16504, "{}", major_version);
+            assert_eq!(pg_type.typarray(), 0);
+            assert_eq!(pg_type.typalign(), TypeAlign::Double, "{}", major_version);
+            assert_eq!(pg_type.typstorage(), TypeStorage::Extended);
+            assert_eq!(pg_type.typtypmod(), -1);
+        }
+    }
+
+    fn database(oid: u32, dattablespace: u32) -> DatabaseRow {
+        DatabaseRow {
+            oid,
+            datname: "shop".to_string(),
+            datistemplate: false,
+            datallowconn: true,
+            datfrozenxid: TransactionId(722),
+            datminmxid: 1,
+            dattablespace,
+        }
+    }
+
+    /// A `pg_filenode.map` of PostgreSQL 16 onwards holding `mappings`.
+    fn relmap(mappings: &[(u32, u32)]) -> RelMapFile {
+        let mut bytes = vec![0u8; 524];
+        bytes[0..4].copy_from_slice(&crate::relmap::RELMAPPER_FILEMAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&(mappings.len() as u32).to_le_bytes());
+        for (slot, (oid, filenode)) in mappings.iter().enumerate() {
+            bytes[8 + slot * 8..12 + slot * 8].copy_from_slice(&oid.to_le_bytes());
+            bytes[12 + slot * 8..16 + slot * 8].copy_from_slice(&filenode.to_le_bytes());
+        }
+        RelMapFile::from_bytes(&bytes, Endianness::LittleEndian).unwrap()
+    }
+
+    #[test]
+    fn locates_relation_files() {
+        let data = Path::new("/pgdata");
+        let version = tablespace_version_directory(15, 202209061);
+        assert_eq!(version, "PG_15_202209061");
+        assert_eq!(
+            database_path(data, GLOBAL_TABLESPACE_OID, 0, &version),
+            Path::new("/pgdata/global")
+        );
+        assert_eq!(
+            database_path(data, DEFAULT_TABLESPACE_OID, 16384, &version),
+            Path::new("/pgdata/base/16384")
+        );
+        assert_eq!(
+            database_path(data, 16390, 16384, &version),
+            Path::new("/pgdata/pg_tblspc/16390/PG_15_202209061/16384")
+        );
+
+        // reltablespace zero means the database's default tablespace.
+        let relmap = relmap(&[(1259, 16700)]);
+        let orders = class(16500, "orders", 2200);
+        let path = |database: &DatabaseRow, class: &ClassRow| {
+            relation_path(data, database, class, &version, &relmap)
+        };
+        let shop = database(16384, DEFAULT_TABLESPACE_OID);
+        assert_eq!(
+            path(&shop, &orders).unwrap(),
+            Path::new("/pgdata/base/16384/16500")
+        );
+        let moved = database(16384, 16390);
+        assert_eq!(
+            path(&moved, &orders).unwrap(),
+            Path::new("/pgdata/pg_tblspc/16390/PG_15_202209061/16384/16500")
+        );
+        let mut elsewhere = orders.clone();
+        elsewhere.reltablespace = 16391;
+        assert_eq!(
+            relation_locator(&moved, &elsewhere, &relmap).unwrap(),
+            RelFileLocator::new(16391, 16384, 16500)
+        );
+
+        // A zero relfilenode is looked up in the map; shared relations live in global.
+        let mut pg_class = class(1259, "pg_class", 11);
+        pg_class.relfilenode = 0;
+        assert_eq!(
+            path(&moved, &pg_class).unwrap(),
+            Path::new("/pgdata/pg_tblspc/16390/PG_15_202209061/16384/16700")
+        );
+        let mut pg_database = class(1262, "pg_database", 11);
+        pg_database.relisshared = true;
+        assert_eq!(
+            relation_locator(&moved, &pg_database, &relmap).unwrap(),
+            RelFileLocator::new(GLOBAL_TABLESPACE_OID, 0, 1262)
+        );
+        pg_database.relfilenode = 0;
+        let err = path(&shop, &pg_database).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let mut temp = orders.clone();
+        temp.relpersistence = RelPersistence::Temp;
+        assert_eq!(
+            path(&shop, &temp).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+        for relkind in [
+            RelKind::View,
+            RelKind::PartitionedTable,
+            RelKind::CompositeType,
+        ] {
+            let mut view = orders.clone();
+            view.relkind = relkind;
+            assert_eq!(
+                path(&shop, &view).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+    }
+
+    fn domain(oid: u32, typbasetype: u32) -> TypeRow {
+        TypeRow {
+            oid,
+            typname: format!("domain_{}", oid),
+            typnamespace: 2200,
+            typlen: -1,
+            typbyval: false,
+            typtype: TypeType::Domain,
+            typrelid: 0,
+            typelem: 0,
+            typarray: 0,
+            typalign: TypeAlign::Int,
+            typstorage: TypeStorage::Main,
+            typbasetype,
+            typtypmod: -1,
+            typcollation: 0,
+        }
+    }
+
+    #[test]
+    fn resolves_domains_over_domains() {
+        let mut orders = class(16500, "orders", 2200);
+        orders.relnatts = 1;
+        let mut total = attribute(1, "total", 16602, -1);
+        total.attrelid = 16500;
+        let catalog = Catalog::from_rows(
+            vec![namespace(2200, "public")],
+            vec![orders],
+            vec![total],
+            // 16602 over 16601 over numeric; 16603 and 16604 form a cycle.
+            vec![
+                domain(16601, oid::NUMERICOID),
+                domain(16602, 16601),
+                domain(16603, 16604),
+                domain(16604, 16603),
+            ],
+        );
+        assert_eq!(catalog.storage_type(16602), oid::NUMERICOID);
+        assert_eq!(catalog.storage_type(16601), oid::NUMERICOID);
+        assert_eq!(catalog.storage_type(oid::INT4OID), oid::INT4OID);
+        assert!([16603, 16604].contains(&catalog.storage_type(16603)));
+
+        let orders = catalog.relation("public", "orders").unwrap();
+        let descriptor = catalog.tuple_descriptor(orders);
+        assert_eq!(descriptor.attributes()[0].type_oid(), oid::NUMERICOID);
+    }
+
+    #[test]
+    fn decodes_each_database_layout() {
+        for major_version in [14, 15, 17] {
+            let row = |oid: u32, datname: &'static str, datallowconn: bool, spcoid: u32| {
+                let mut fields = vec![
+                    Field::Oid(oid),
+                    Field::Name(datname),
+                    Field::Oid(10),
+                    Field::Int4(6),
+                ];
+                if major_version >= 15 {
+                    // datlocprovider
+                    fields.push(Field::Char(b'c'));
+                } else {
+                    // datcollate, datctype
+                    fields.extend([Field::Name("C.UTF-8"), Field::Name("C.UTF-8")]);
+                }
+                fields.extend([Field::Bool(oid == 4), Field::Bool(datallowconn)]);
+                if major_version >= 17 {
+                    // dathasloginevt
+                    fields.push(Field::Bool(false));
+                }
+                fields.push(Field::Int4(-1));
+                if major_version < 15 {
+                    // datlastsysoid
+                    fields.push(Field::Oid(13000));
+                }
+                fields.extend([Field::Oid(722), Field::Oid(1), Field::Oid(spcoid)]);
+                tuple(&fields)
+            };
+            let (_dir, reader) = catalog_reader(
+                major_version,
+                &[(
+                    DATABASE_RELATION_ID,
+                    vec![
+                        row(4, "template0", false, DEFAULT_TABLESPACE_OID),
+                        row(16384, "shop", true, 16390),
+                    ],
+                )],
+            );
+
+            let databases = reader.databases().unwrap();
+            assert_eq!(databases.len(), 2);
+            let template0 = &databases[0];
+            assert_eq!(template0.datname(), "template0", "{}", major_version);
+            assert!(template0.datistemplate());
+            assert!(!template0.datallowconn(), "{}", major_version);
+            let shop = &databases[1];
+            assert_eq!(shop.oid(), 16384);
+            assert_eq!(shop.datname(), "shop");
+            assert!(!shop.datistemplate(), "{}", major_version);
+            assert!(shop.datallowconn(), "{}", major_version);
+            assert_eq!(shop.datfrozenxid(), TransactionId(722), "{}", major_version);
+            assert_eq!(shop.datminmxid(), 1);
+            assert_eq!(shop.dattablespace(), 16390, "{}", major_version);
+        }
+    }
+
+    #[test]
+    fn separates_uncommitted_classes() {
+        // Transaction 900 is still running: it created "staging" and truncated
+        // "orders", giving it filenode 16510. Transaction 901 aborted.
+        let row = |oid: u32, relname: &str, relfilenode: u32, xmin: u32, xmax: u32| {
+            let mut fields = vec![Field::Oid(oid), Field::Name(relname)];
+            fields.extend([2200, 0, 0, 10, 2, relfilenode, 0].map(Field::Oid));
+            fields.extend([Field::Int4(0), Field::Float4(-1.0), Field::Int4(0)]);
+            fields.extend([Field::Oid(0), Field::Bool(false), Field::Bool(false)]);
+            fields.extend([Field::Char(b'p'), Field::Char(b'r'), Field::Int2(1)]);
+            heap_tuple(
+                xmin,
+                xmax,
+                Infomask::empty(),
+                fields.len() as u16,
+                &fill(&fields),
+            )
+        };
+        let (_dir, mut reader) = catalog_reader(
+            15,
+            &[(
+                RELATION_RELATION_ID,
+                vec![
+                    row(16500, "orders", 16501, 800, 900),
+                    row(16500, "orders", 16510, 900, 0),
+                    row(16520, "staging", 16520, 900, 0),
+                    row(16530, "discarded", 16530, 901, 0),
+                ],
+            )],
+        );
+        reader.oracle = Box::new(|xid: TransactionId| {
+            Ok(match *xid {
+                900 => crate::visibility::TransactionStatus::InProgress,
+                901 => crate::visibility::TransactionStatus::Aborted,
+                _ => crate::visibility::TransactionStatus::Committed,
+            })
+        });
+
+        let filenodes = |classes: Vec<ClassRow>| -> Vec<(String, u32)> {
+            classes
+                .into_iter()
+                .map(|class| (class.relname, class.relfilenode))
+                .collect()
+        };
+        assert_eq!(
+            filenodes(reader.classes().unwrap()),
+            [("orders".to_string(), 16501)]
+        );
+        assert_eq!(
+            filenodes(reader.uncommitted_classes().unwrap()),
+            [
+                ("orders".to_string(), 16510),
+                ("staging".to_string(), 16520)
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! Port of PostgreSQL's `pg_checksum_page` (src/include/storage/checksum_impl.h).
+//!
+//! The page is treated as an array of 32-bit words spread over 32 parallel
+//! FNV-1a-like sums, which are folded together and mixed with the block number.
+
+use crate::{BlockNumber, Endianness};
+
+/// Number of parallel sums, chosen upstream so the loop vectorizes well.
+const N_SUMS: usize = 32;
+
+/// 32-bit FNV prime.
+const FNV_PRIME: u32 = 16777619;
+
+/// Random starting values for each of the parallel sums.
+const CHECKSUM_BASE_OFFSETS: [u32; N_SUMS] = [
+    0x5B1F36E9, 0xB8525960, 0x02AB50AA, 0x1DE66D2A, 0x79FF467A, 0x9BB9F8A3, 0x217E7CD2, 0x83E13D2C,
+    0xF8D4474F, 0xE39EB970, 0x42C6AE16, 0x993216FA, 0x7B093B5D, 0x98DAFF3C, 0xF718902A, 0x0B1C9CDB,
+    0xE58F764B, 0x187636BC, 0x5D7B3BB1, 0xE73DE7DE, 0x92BEC979, 0xCCA6C0B2, 0x304A0979, 0x85AA43D4,
+    0x783125BB, 0x6CA8EAA2, 0xE407EAC6, 0x4B5CFC3E, 0x9FBF8C76, 0x15CA20BE, 0xF2CA9FD3, 0x959BD756,
+];
+
+/// Byte range of `pd_checksum` within the page header.
+const PD_CHECKSUM_OFFSET: usize = 8;
+
+/// Outcome of verifying a page against its stored checksum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChecksumStatus {
+    /// The stored checksum matches, or the page is new and has none.
+    Valid,
+    /// The stored checksum does not match the page contents.
+    Invalid { stored: u16, computed: u16 },
+    /// The page was written without data checksums: `pg_control` says so or,
+    /// without it to go by, `pd_checksum` is zero.
+    Disabled,
+}
+
+#[inline]
+fn checksum_comp(checksum: &mut u32, value: u32) {
+    let tmp = *checksum ^ value;
+    *checksum = tmp.wrapping_mul(FNV_PRIME) ^ (tmp >> 17);
+}
+
+/// Computes the raw 32-bit checksum of a block, with `pd_checksum` treated as zero.
+fn pg_checksum_block(page: &[u8], endianness: Endianness) -> u32 {
+    let mut sums = CHECKSUM_BASE_OFFSETS;
+
+    for (index, word) in page.chunks_exact(4).enumerate() {
+        let bytes = [word[0], word[1], word[2], word[3]];
+        let mut value = match endianness {
+            Endianness::LittleEndian => u32::from_le_bytes(bytes),
+            Endianness::BigEndian => u32::from_be_bytes(bytes),
+        };
+        // pd_checksum occupies half of the third word; it is excluded from the sum.
+        if index == PD_CHECKSUM_OFFSET / 4 {
+            value = match endianness {
+                Endianness::LittleEndian => u32::from_le_bytes([0, 0, bytes[2], bytes[3]]),
+                Endianness::BigEndian => u32::from_be_bytes([0, 0, bytes[2], bytes[3]]),
+            };
+        }
+        checksum_comp(&mut sums[index % N_SUMS], value);
+    }
+
+    // Two rounds of zeroes for additional mixing.
+    for _ in 0..2 {
+        for sum in sums.iter_mut() {
+            checksum_comp(sum, 0);
+        }
+    }
+
+    sums.iter().fold(0, |result, sum| result ^ sum)
+}
+
+/// Computes the 16-bit checksum PostgreSQL stores in `pd_checksum` for `page`.
+///
+/// `page` must be a whole block; its length must be a multiple of 128 bytes.
+pub fn pg_checksum_page(page: &[u8], block_number: BlockNumber, endianness: Endianness) -> u16 {
+    debug_assert_eq!(page.len() % (4 * N_SUMS), 0);
+    let checksum = pg_checksum_block(page, endianness) ^ *block_number;
+
+    // Reduce to a uint16 with an offset of one, so zero is never a valid checksum.
+    ((checksum % 65535) + 1) as u16
+}
+
+/// Verifies the checksum stored in `page`, the `block_number`th block of its relation.
+///
+/// The block number is counted from the start of the relation, not the segment file.
+pub fn verify_page_checksum(
+    page: &[u8],
+    block_number: BlockNumber,
+    endianness: Endianness,
+) -> ChecksumStatus {
+    if page.iter().all(|&byte| byte == 0) {
+        return ChecksumStatus::Valid;
+    }
+
+    if stored_checksum(page, endianness) == 0 {
+        return ChecksumStatus::Disabled;
+    }
+    compare_checksum(page, block_number, endianness)
+}
+
+fn stored_checksum(page: &[u8], endianness: Endianness) -> u16 {
+    let raw = [page[PD_CHECKSUM_OFFSET], page[PD_CHECKSUM_OFFSET + 1]];
+    match endianness {
+        Endianness::LittleEndian => u16::from_le_bytes(raw),
+        Endianness::BigEndian => u16::from_be_bytes(raw),
+    }
+}
+
+/// Compares the stored checksum of a page that is not all zeroes with the computed one.
+fn compare_checksum(
+    page: &[u8],
+    block_number: BlockNumber,
+    endianness: Endianness,
+) -> ChecksumStatus {
+    let stored = stored_checksum(page, endianness);
+    let computed = pg_checksum_page(page, block_number, endianness);
+    if stored == computed {
+        ChecksumStatus::Valid
+    } else {
+        ChecksumStatus::Invalid { stored, computed }
+    }
+}
+
+/// Verifies `page` in a cluster whose `pg_control` records `data_checksum_version`.
+///
+/// Unlike [`verify_page_checksum`], which has to guess, a zero `pd_checksum` is
+/// a mismatch when the cluster has checksums on, as the server reports it; with
+/// them off, every initialized page is `Disabled` whatever it stores.
+pub fn verify_cluster_page_checksum(
+    page: &[u8],
+    block_number: BlockNumber,
+    endianness: Endianness,
+    data_checksum_version: u32,
+) -> ChecksumStatus {
+    if page.iter().all(|&byte| byte == 0) {
+        return ChecksumStatus::Valid;
+    }
+    if data_checksum_version == 0 {
+        return ChecksumStatus::Disabled;
+    }
+    compare_checksum(page, block_number, endianness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_detects_flipped_bits() {
+        let mut page = vec![0u8; crate::DEFAULT_POSTGRES_PAGE_SIZE];
+        page[12..14].copy_from_slice(&28u16.to_le_bytes());
+        page[14..16].copy_from_slice(&8160u16.to_le_bytes());
+        page[16..18].copy_from_slice(&8192u16.to_le_bytes());
+        page[18..20].copy_from_slice(&0x2004u16.to_le_bytes());
+        page[8160..8192].fill(0x5a);
+
+        let block = BlockNumber(7);
+        let checksum = pg_checksum_page(&page, block, Endianness::LittleEndian);
+        page[8..10].copy_from_slice(&checksum.to_le_bytes());
+        assert_eq!(
+            verify_page_checksum(&page, block, Endianness::LittleEndian),
+            ChecksumStatus::Valid
+        );
+
+        // The same bytes at another block number must not verify.
+        assert!(matches!(
+            verify_page_checksum(&page, BlockNumber(8), Endianness::LittleEndian),
+            ChecksumStatus::Invalid { .. }
+        ));
+
+        page[8170] ^= 0x01;
+        assert_eq!(
+            verify_page_checksum(&page, block, Endianness::LittleEndian),
+            ChecksumStatus::Invalid {
+                stored: checksum,
+                computed: pg_checksum_page(&page, block, Endianness::LittleEndian),
+            }
+        );
+
+        page[8..10].fill(0);
+        assert_eq!(
+            verify_page_checksum(&page, block, Endianness::LittleEndian),
+            ChecksumStatus::Disabled
+        );
+    }
+
+    #[test]
+    fn matches_postgres() {
+        // Block 0 of a table holding one int4 row, written by PostgreSQL 15 on
+        // x86_64 with data checksums on.
+        let mut page = vec![0u8; crate::DEFAULT_POSTGRES_PAGE_SIZE];
+        page[..28].copy_from_slice(&[
+            0x00, 0x00, 0x00, 0x00, 0x10, 0x52, 0x8f, 0x02, 0x00, 0x3b, 0x04, 0x00, 0x1c, 0x00,
+            0xe0, 0x1f, 0x00, 0x20, 0x04, 0x20, 0x00, 0x00, 0x00, 0x00, 0xe0, 0x9f, 0x38, 0x00,
+        ]);
+        page[8160..8164].copy_from_slice(&[0x1b, 0x03, 0x00, 0x00]);
+        page[8176..8188].copy_from_slice(&[
+            0x01, 0x00, 0x01, 0x00, 0x00, 0x0b, 0x18, 0x00, 0x2a, 0x00, 0x00, 0x00,
+        ]);
+
+        let block = BlockNumber(0);
+        assert_eq!(
+            pg_checksum_page(&page, block, Endianness::LittleEndian),
+            0x3b00
+        );
+        assert_eq!(
+            verify_cluster_page_checksum(&page, block, Endianness::LittleEndian, 1),
+            ChecksumStatus::Valid
+        );
+        assert_eq!(
+            verify_cluster_page_checksum(&page, block, Endianness::LittleEndian, 0),
+            ChecksumStatus::Disabled
+        );
+
+        // With checksums on, a zeroed pd_checksum is corruption, not a page
+        // written without one.
+        page[8..10].fill(0);
+        assert_eq!(
+            verify_cluster_page_checksum(&page, block, Endianness::LittleEndian, 1),
+            ChecksumStatus::Invalid {
+                stored: 0,
+                computed: 0x3b00
+            }
+        );
+        assert_eq!(
+            verify_cluster_page_checksum(&vec![0u8; 8192], block, Endianness::LittleEndian, 1),
+            ChecksumStatus::Valid
+        );
+    }
+}
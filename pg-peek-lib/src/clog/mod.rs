@@ -0,0 +1,139 @@
+//! Transaction commit status from `pg_xact`, formerly `pg_clog` (`clog.c`).
+//!
+//! Each transaction has two status bits, four to a byte, so a page covers
+//! `BLCKSZ * 4` xids. The whole 32-bit xid space maps onto pages directly, so
+//! wraparound needs no special casing: xid 0 and xid 2^32 share a slot.
+
+use crate::slru::SlruReader;
+use crate::visibility::{TransactionOracle, TransactionStatus};
+use crate::TransactionId;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Bits of status per transaction (`CLOG_BITS_PER_XACT`).
+const CLOG_BITS_PER_XACT: u32 = 2;
+/// Transactions per status byte (`CLOG_XACTS_PER_BYTE`).
+const CLOG_XACTS_PER_BYTE: u32 = 4;
+const CLOG_XACT_BITMASK: u8 = (1 << CLOG_BITS_PER_XACT) - 1;
+
+/// Reads transaction status from a `pg_xact` directory.
+pub struct ClogReader {
+    slru: SlruReader,
+}
+
+impl ClogReader {
+    /// Reads the `pg_xact` directory itself, whose pages are `block_size` bytes.
+    pub fn new(dir: &Path, block_size: usize) -> ClogReader {
+        ClogReader {
+            slru: SlruReader::new(dir, block_size),
+        }
+    }
+
+    /// Reads `pg_xact` of a data directory, or `pg_clog` before PostgreSQL 10.
+    pub fn open(data_directory: &Path, block_size: usize) -> ClogReader {
+        let dir = data_directory.join("pg_xact");
+        let dir = if !dir.is_dir() && data_directory.join("pg_clog").is_dir() {
+            data_directory.join("pg_clog")
+        } else {
+            dir
+        };
+        ClogReader::new(&dir, block_size)
+    }
+
+    fn xacts_per_page(&self) -> u32 {
+        self.slru.block_size() as u32 * CLOG_XACTS_PER_BYTE
+    }
+
+    /// Page holding `xid`'s status (`TransactionIdToPage`).
+    pub fn page_of(&self, xid: TransactionId) -> u32 {
+        xid.0 / self.xacts_per_page()
+    }
+
+    /// Path of the segment file holding `xid`'s status.
+    pub fn segment_path(&self, xid: TransactionId) -> PathBuf {
+        self.slru.segment_path(self.page_of(xid))
+    }
+
+    /// Status of `xid` as recorded in `pg_xact` (`TransactionIdGetStatus`).
+    ///
+    /// The permanent xids are answered without reading anything, as
+    /// `TransactionLogFetch` does.
+    pub fn status(&self, xid: TransactionId) -> io::Result<TransactionStatus> {
+        if !xid.is_normal() {
+            return Ok(if xid == TransactionId::INVALID {
+                TransactionStatus::Aborted
+            } else {
+                TransactionStatus::Committed
+            });
+        }
+
+        let entry = xid.0 % self.xacts_per_page();
+        let byte = (entry / CLOG_XACTS_PER_BYTE) as usize;
+        let shift = (entry % CLOG_XACTS_PER_BYTE) * CLOG_BITS_PER_XACT;
+        let bits = self.slru.with_page(self.page_of(xid), |page| {
+            (page[byte] >> shift) & CLOG_XACT_BITMASK
+        })?;
+
+        Ok(match bits {
+            0 => TransactionStatus::InProgress,
+            1 => TransactionStatus::Committed,
+            2 => TransactionStatus::Aborted,
+            _ => TransactionStatus::SubCommitted,
+        })
+    }
+}
+
+impl TransactionOracle for ClogReader {
+    fn status(&self, xid: TransactionId) -> io::Result<TransactionStatus> {
+        ClogReader::status(self, xid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_status_bits_across_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let block_size = 8192;
+        let per_segment = block_size as u32 * CLOG_XACTS_PER_BYTE * 32;
+
+        // Segment 0000: xids 4..=7 share byte 1; segment 0001 holds its first xid.
+        let mut segment = vec![0u8; block_size];
+        segment[1] = 0b11_10_01_00;
+        std::fs::write(dir.path().join("0000"), &segment).unwrap();
+        let mut next = vec![0u8; block_size];
+        next[0] = 0b01;
+        std::fs::write(dir.path().join("0001"), &next).unwrap();
+
+        let clog = ClogReader::new(dir.path(), block_size);
+        let statuses: Vec<_> = (4..8)
+            .map(|xid| clog.status(TransactionId(xid)).unwrap())
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                TransactionStatus::InProgress,
+                TransactionStatus::Committed,
+                TransactionStatus::Aborted,
+                TransactionStatus::SubCommitted
+            ]
+        );
+        // A page past the end of the segment has not been written yet.
+        assert_eq!(
+            clog.status(TransactionId(block_size as u32 * 4 + 3))
+                .unwrap(),
+            TransactionStatus::InProgress
+        );
+        assert_eq!(
+            clog.status(TransactionId(per_segment)).unwrap(),
+            TransactionStatus::Committed
+        );
+        assert!(clog.status(TransactionId(2 * per_segment)).is_err());
+        assert_eq!(
+            clog.segment_path(TransactionId(u32::MAX)),
+            dir.path().join("0FFF")
+        );
+    }
+}
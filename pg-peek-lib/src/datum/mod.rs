@@ -0,0 +1,803 @@
+//! Decoding of tuple user data into typed values, driven by a tuple descriptor.
+
+use crate::types::{PgType, TypeAlign};
+use crate::varlena::{CompressionMethod, VarattExternal, Varlena, VarlenaHeader};
+use crate::{att_isnull, Endianness, HeapTuple};
+use std::fmt;
+use std::io;
+
+/// Well-known type OIDs from `pg_type.dat`.
+pub mod oid {
+    pub const BOOLOID: u32 = 16;
+    pub const BYTEAOID: u32 = 17;
+    pub const CHAROID: u32 = 18;
+    pub const NAMEOID: u32 = 19;
+    pub const INT8OID: u32 = 20;
+    pub const INT2OID: u32 = 21;
+    pub const INT4OID: u32 = 23;
+    pub const REGPROCOID: u32 = 24;
+    pub const TEXTOID: u32 = 25;
+    pub const OIDOID: u32 = 26;
+    pub const XIDOID: u32 = 28;
+    pub const CIDOID: u32 = 29;
+    pub const JSONOID: u32 = 114;
+    pub const XMLOID: u32 = 142;
+    pub const FLOAT4OID: u32 = 700;
+    pub const FLOAT8OID: u32 = 701;
+    pub const BPCHAROID: u32 = 1042;
+    pub const VARCHAROID: u32 = 1043;
+    pub const DATEOID: u32 = 1082;
+    pub const TIMEOID: u32 = 1083;
+    pub const TIMESTAMPOID: u32 = 1114;
+    pub const TIMESTAMPTZOID: u32 = 1184;
+    pub const NUMERICOID: u32 = 1700;
+    pub const REGCLASSOID: u32 = 2205;
+    pub const REGTYPEOID: u32 = 2206;
+    pub const UUIDOID: u32 = 2950;
+}
+
+/// How one column is stored: the parts of `pg_type` that drive tuple layout.
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    type_oid: u32,
+    typlen: i16,
+    typbyval: bool,
+    typalign: TypeAlign,
+}
+
+impl Attribute {
+    pub fn new(type_oid: u32, typlen: i16, typbyval: bool, typalign: TypeAlign) -> Attribute {
+        Attribute {
+            type_oid,
+            typlen,
+            typbyval,
+            typalign,
+        }
+    }
+
+    pub fn from_pg_type(pg_type: &PgType) -> Attribute {
+        Attribute::new(
+            pg_type.oid(),
+            pg_type.length(),
+            pg_type.by_value(),
+            pg_type.align(),
+        )
+    }
+
+    pub fn type_oid(&self) -> u32 {
+        self.type_oid
+    }
+
+    pub fn typlen(&self) -> i16 {
+        self.typlen
+    }
+
+    pub fn typbyval(&self) -> bool {
+        self.typbyval
+    }
+
+    pub fn typalign(&self) -> TypeAlign {
+        self.typalign
+    }
+}
+
+/// The column layout of a relation, in attribute number order.
+#[derive(Debug, Clone, Default)]
+pub struct TupleDescriptor {
+    attributes: Vec<Attribute>,
+}
+
+impl TupleDescriptor {
+    pub fn new(attributes: Vec<Attribute>) -> TupleDescriptor {
+        TupleDescriptor { attributes }
+    }
+
+    /// Builds a descriptor from column type OIDs resolved through the bundled `pg_type`.
+    pub fn from_type_oids(type_oids: &[u32]) -> io::Result<TupleDescriptor> {
+        let attributes = type_oids
+            .iter()
+            .map(|&type_oid| {
+                crate::types::pg_type_by_oid(type_oid)
+                    .map(Attribute::from_pg_type)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("Unknown type OID {}", type_oid),
+                        )
+                    })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(TupleDescriptor { attributes })
+    }
+
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    pub fn len(&self) -> usize {
+        self.attributes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty()
+    }
+}
+
+/// A decoded column value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Datum {
+    Null,
+    Bool(bool),
+    /// The single-byte `"char"` type.
+    Char(i8),
+    Int2(i16),
+    Int4(i32),
+    Int8(i64),
+    Float4(f32),
+    Float8(f64),
+    /// `oid`, `xid`, `cid` and the `reg*` types.
+    Oid(u32),
+    /// `text`, `varchar`, `bpchar`, `name`, `json` and `xml`.
+    Text(String),
+    Bytea(Vec<u8>),
+    /// `numeric`, rendered the way `numeric_out` does.
+    Numeric(String),
+    /// Days since 2000-01-01.
+    Date(i32),
+    /// Microseconds since midnight.
+    Time(i64),
+    /// Microseconds since 2000-01-01 00:00:00.
+    Timestamp(i64),
+    /// Microseconds since 2000-01-01 00:00:00 UTC.
+    TimestampTz(i64),
+    Uuid([u8; 16]),
+    /// A pointer to a value stored out of line in a TOAST table.
+    External(VarattExternal),
+    /// A value of a type this crate does not decode, or one stored in a form it cannot read yet.
+    Raw(Vec<u8>),
+}
+
+/// Decodes every column of `descriptor` from a tuple's user data.
+///
+/// Columns past `natts` read as null, as do columns marked null in the bitmap.
+pub fn decode_tuple(
+    descriptor: &TupleDescriptor,
+    data: &[u8],
+    null_bitmap: Option<&[u8]>,
+    natts: u16,
+    endianness: Endianness,
+) -> io::Result<Vec<Datum>> {
+    let mut values = Vec::with_capacity(descriptor.len());
+    for_each_attribute(
+        descriptor,
+        data,
+        null_bitmap,
+        natts,
+        endianness,
+        |attribute, bytes| {
+            values.push(match bytes {
+                Some(bytes) => decode_datum(attribute, bytes, endianness)?,
+                None => Datum::Null,
+            });
+            Ok(())
+        },
+    )?;
+    Ok(values)
+}
+
+/// Reports how each attribute of a tuple is compressed, inline or in its TOAST table.
+///
+/// Takes the same arguments as [`decode_tuple`]; nulls, fixed-width values and
+/// uncompressed varlenas are `None`.
+pub fn tuple_compression(
+    descriptor: &TupleDescriptor,
+    data: &[u8],
+    null_bitmap: Option<&[u8]>,
+    natts: u16,
+    endianness: Endianness,
+) -> io::Result<Vec<Option<CompressionMethod>>> {
+    let mut methods = Vec::with_capacity(descriptor.len());
+    for_each_attribute(
+        descriptor,
+        data,
+        null_bitmap,
+        natts,
+        endianness,
+        |attribute, bytes| {
+            methods.push(match bytes {
+                Some(bytes) if attribute.typlen == -1 => {
+                    Varlena::parse(bytes, endianness)?.compression_method()
+                }
+                _ => None,
+            });
+            Ok(())
+        },
+    )?;
+    Ok(methods)
+}
+
+/// Walks the attributes of a tuple, passing each one's bytes, or `None` if it is null.
+fn for_each_attribute<F>(
+    descriptor: &TupleDescriptor,
+    data: &[u8],
+    null_bitmap: Option<&[u8]>,
+    natts: u16,
+    endianness: Endianness,
+    mut f: F,
+) -> io::Result<()>
+where
+    F: FnMut(&Attribute, Option<&[u8]>) -> io::Result<()>,
+{
+    let mut offset = 0;
+
+    for (index, attribute) in descriptor.attributes.iter().enumerate() {
+        if att_isnull(null_bitmap, natts, index) {
+            f(attribute, None)?;
+            continue;
+        }
+
+        // A varlena with a one-byte header is never aligned; a zero byte is padding.
+        let unaligned_varlena =
+            attribute.typlen == -1 && data.get(offset).is_some_and(|&byte| byte != 0);
+        if !unaligned_varlena {
+            offset = align(offset, attribute.typalign);
+        }
+
+        let length = attribute_length(attribute, &data[offset.min(data.len())..], endianness)?;
+        let bytes = data.get(offset..offset + length).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Attribute {} at offset {} with length {} runs past the tuple",
+                    index + 1,
+                    offset,
+                    length
+                ),
+            )
+        })?;
+        f(attribute, Some(bytes))?;
+        offset += length;
+    }
+
+    Ok(())
+}
+
+impl HeapTuple {
+    /// Decodes the tuple's user data into one value per attribute of `descriptor`.
+    pub fn decode(
+        &self,
+        descriptor: &TupleDescriptor,
+        endianness: Endianness,
+    ) -> io::Result<Vec<Datum>> {
+        decode_tuple(
+            descriptor,
+            self.data(),
+            self.null_bitmap(),
+            self.header().natts(),
+            endianness,
+        )
+    }
+
+    /// Reports how each attribute of `descriptor` is compressed; see [`tuple_compression`].
+    pub fn compression(
+        &self,
+        descriptor: &TupleDescriptor,
+        endianness: Endianness,
+    ) -> io::Result<Vec<Option<CompressionMethod>>> {
+        tuple_compression(
+            descriptor,
+            self.data(),
+            self.null_bitmap(),
+            self.header().natts(),
+            endianness,
+        )
+    }
+}
+
+fn align(offset: usize, typalign: TypeAlign) -> usize {
+    let alignment = typalign.alignment();
+    (offset + alignment - 1) & !(alignment - 1)
+}
+
+/// Number of bytes the attribute occupies at the start of `data`.
+fn attribute_length(
+    attribute: &Attribute,
+    data: &[u8],
+    endianness: Endianness,
+) -> io::Result<usize> {
+    match attribute.typlen {
+        -1 => Ok(Varlena::parse(data, endianness)?.size()),
+        -2 => data
+            .iter()
+            .position(|&byte| byte == 0)
+            .map(|nul| nul + 1)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Unterminated cstring attribute")
+            }),
+        length if length > 0 => Ok(length as usize),
+        length => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid typlen {}", length),
+        )),
+    }
+}
+
+fn fixed<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut array = [0u8; N];
+    array.copy_from_slice(&bytes[..N]);
+    array
+}
+
+macro_rules! from_bytes {
+    ($ty:ty, $bytes:expr, $endianness:expr) => {
+        match $endianness {
+            Endianness::LittleEndian => <$ty>::from_le_bytes(fixed($bytes)),
+            Endianness::BigEndian => <$ty>::from_be_bytes(fixed($bytes)),
+        }
+    };
+}
+
+/// Decodes the plain bytes of a varlena value, header stripped and decompressed.
+pub(crate) fn decode_varlena_payload(
+    attribute: &Attribute,
+    payload: &[u8],
+    endianness: Endianness,
+) -> io::Result<Datum> {
+    use oid::*;
+
+    Ok(match attribute.type_oid {
+        TEXTOID | VARCHAROID | BPCHAROID | JSONOID | XMLOID => {
+            Datum::Text(String::from_utf8_lossy(payload).into_owned())
+        }
+        BYTEAOID => Datum::Bytea(payload.to_vec()),
+        NUMERICOID => Datum::Numeric(numeric_to_string(payload, endianness)?),
+        _ => Datum::Raw(payload.to_vec()),
+    })
+}
+
+/// Decodes the bytes of one non-null attribute.
+fn decode_datum(attribute: &Attribute, bytes: &[u8], endianness: Endianness) -> io::Result<Datum> {
+    use oid::*;
+
+    if attribute.typlen == -1 {
+        let varlena = Varlena::parse(bytes, endianness)?;
+        if let VarlenaHeader::External(external) = varlena.header() {
+            return Ok(Datum::External(external));
+        }
+        return decode_varlena_payload(attribute, &varlena.value()?, endianness);
+    }
+
+    if attribute.type_oid == NAMEOID || attribute.typlen == -2 {
+        let end = bytes
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(bytes.len());
+        return Ok(Datum::Text(
+            String::from_utf8_lossy(&bytes[..end]).into_owned(),
+        ));
+    }
+
+    let datum = match (attribute.type_oid, bytes.len()) {
+        (BOOLOID, 1) => Datum::Bool(bytes[0] != 0),
+        (CHAROID, 1) => Datum::Char(bytes[0] as i8),
+        (INT2OID, 2) => Datum::Int2(from_bytes!(i16, bytes, endianness)),
+        (INT4OID, 4) => Datum::Int4(from_bytes!(i32, bytes, endianness)),
+        (INT8OID, 8) => Datum::Int8(from_bytes!(i64, bytes, endianness)),
+        (FLOAT4OID, 4) => Datum::Float4(from_bytes!(f32, bytes, endianness)),
+        (FLOAT8OID, 8) => Datum::Float8(from_bytes!(f64, bytes, endianness)),
+        (OIDOID | XIDOID | CIDOID | REGPROCOID | REGCLASSOID | REGTYPEOID, 4) => {
+            Datum::Oid(from_bytes!(u32, bytes, endianness))
+        }
+        (DATEOID, 4) => Datum::Date(from_bytes!(i32, bytes, endianness)),
+        (TIMEOID, 8) => Datum::Time(from_bytes!(i64, bytes, endianness)),
+        (TIMESTAMPOID, 8) => Datum::Timestamp(from_bytes!(i64, bytes, endianness)),
+        (TIMESTAMPTZOID, 8) => Datum::TimestampTz(from_bytes!(i64, bytes, endianness)),
+        (UUIDOID, 16) => Datum::Uuid(fixed(bytes)),
+        _ => Datum::Raw(bytes.to_vec()),
+    };
+    Ok(datum)
+}
+
+const DEC_DIGITS: usize = 4;
+const NUMERIC_SIGN_MASK: u16 = 0xC000;
+const NUMERIC_NEG: u16 = 0x4000;
+const NUMERIC_SHORT: u16 = 0x8000;
+const NUMERIC_SPECIAL: u16 = 0xC000;
+const NUMERIC_EXT_SIGN_MASK: u16 = 0xF000;
+const NUMERIC_NAN: u16 = 0xC000;
+const NUMERIC_PINF: u16 = 0xD000;
+const NUMERIC_SHORT_SIGN_MASK: u16 = 0x2000;
+const NUMERIC_SHORT_DSCALE_MASK: u16 = 0x1F80;
+const NUMERIC_SHORT_DSCALE_SHIFT: u16 = 7;
+const NUMERIC_SHORT_WEIGHT_SIGN_MASK: u16 = 0x0040;
+const NUMERIC_SHORT_WEIGHT_MASK: u16 = 0x003F;
+const NUMERIC_DSCALE_MASK: u16 = 0x3FFF;
+
+/// Renders the payload of a `numeric` varlena like `numeric_out`.
+fn numeric_to_string(payload: &[u8], endianness: Endianness) -> io::Result<String> {
+    let truncated = || io::Error::new(io::ErrorKind::InvalidData, "Truncated numeric");
+    let header = from_bytes!(u16, payload.get(..2).ok_or_else(truncated)?, endianness);
+
+    let (negative, dscale, weight, digits_start) = match header & NUMERIC_SIGN_MASK {
+        NUMERIC_SPECIAL => {
+            return Ok(match header & NUMERIC_EXT_SIGN_MASK {
+                NUMERIC_NAN => "NaN",
+                NUMERIC_PINF => "Infinity",
+                _ => "-Infinity",
+            }
+            .to_string());
+        }
+        NUMERIC_SHORT => {
+            let weight = (header & NUMERIC_SHORT_WEIGHT_MASK) as i16;
+            let weight = if header & NUMERIC_SHORT_WEIGHT_SIGN_MASK != 0 {
+                weight | !(NUMERIC_SHORT_WEIGHT_MASK as i16)
+            } else {
+                weight
+            };
+            (
+                header & NUMERIC_SHORT_SIGN_MASK != 0,
+                ((header & NUMERIC_SHORT_DSCALE_MASK) >> NUMERIC_SHORT_DSCALE_SHIFT) as usize,
+                weight as i64,
+                2,
+            )
+        }
+        sign => {
+            let weight = from_bytes!(i16, payload.get(2..4).ok_or_else(truncated)?, endianness);
+            (
+                sign == NUMERIC_NEG,
+                (header & NUMERIC_DSCALE_MASK) as usize,
+                weight as i64,
+                4,
+            )
+        }
+    };
+
+    let digits: Vec<i64> = payload[digits_start..]
+        .chunks_exact(2)
+        .map(|digit| from_bytes!(i16, digit, endianness) as i64)
+        .collect();
+    let digit_at = |index: i64| -> i64 {
+        if index >= 0 && (index as usize) < digits.len() {
+            digits[index as usize]
+        } else {
+            0
+        }
+    };
+
+    let mut out = String::new();
+    if negative && !digits.is_empty() {
+        out.push('-');
+    }
+    if weight < 0 {
+        out.push('0');
+    } else {
+        for index in 0..=weight {
+            let digit = digit_at(index);
+            if index == 0 {
+                out.push_str(&digit.to_string());
+            } else {
+                out.push_str(&format!("{:04}", digit));
+            }
+        }
+    }
+    if dscale > 0 {
+        out.push('.');
+        let mut fraction = String::new();
+        let mut index = weight + 1;
+        while fraction.len() < dscale {
+            fraction.push_str(&format!("{:0width$}", digit_at(index), width = DEC_DIGITS));
+            index += 1;
+        }
+        out.push_str(&fraction[..dscale]);
+    }
+    Ok(out)
+}
+
+/// Days between 1970-01-01 and 2000-01-01, the PostgreSQL epoch.
+const POSTGRES_EPOCH_DAYS: i64 = 10957;
+const USECS_PER_DAY: i64 = 86_400_000_000;
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian (year, month, day).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// Writes `YYYY-MM-DD` and returns whether the date is BC.
+///
+/// PostgreSQL puts the era after the time and zone, so callers append it.
+fn format_date(f: &mut fmt::Formatter<'_>, days: i64) -> Result<bool, fmt::Error> {
+    let (year, month, day) = civil_from_days(days + POSTGRES_EPOCH_DAYS);
+    let bc = year <= 0;
+    let year = if bc { 1 - year } else { year };
+    write!(f, "{:04}-{:02}-{:02}", year, month, day)?;
+    Ok(bc)
+}
+
+fn format_era(f: &mut fmt::Formatter<'_>, bc: bool) -> fmt::Result {
+    if bc {
+        write!(f, " BC")?;
+    }
+    Ok(())
+}
+
+fn format_time(f: &mut fmt::Formatter<'_>, usecs: i64) -> fmt::Result {
+    let seconds = usecs / 1_000_000;
+    write!(
+        f,
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )?;
+    let fraction = usecs % 1_000_000;
+    if fraction != 0 {
+        let digits = format!("{:06}", fraction);
+        write!(f, ".{}", digits.trim_end_matches('0'))?;
+    }
+    Ok(())
+}
+
+/// Writes a timestamp followed by `zone`, which is empty for `timestamp`.
+fn format_timestamp(f: &mut fmt::Formatter<'_>, usecs: i64, zone: &str) -> fmt::Result {
+    match usecs {
+        i64::MAX => write!(f, "infinity"),
+        i64::MIN => write!(f, "-infinity"),
+        _ => {
+            let bc = format_date(f, usecs.div_euclid(USECS_PER_DAY))?;
+            write!(f, " ")?;
+            format_time(f, usecs.rem_euclid(USECS_PER_DAY))?;
+            write!(f, "{}", zone)?;
+            format_era(f, bc)
+        }
+    }
+}
+
+/// Writes a float like `float4out` and `float8out`: the shortest digits that read back
+/// exactly, with an exponent when it is below -4 or at least `max_exponent`.
+fn format_float<T>(f: &mut fmt::Formatter<'_>, value: T, max_exponent: i32) -> fmt::Result
+where
+    T: fmt::Display + fmt::LowerExp + Into<f64> + Copy,
+{
+    let wide: f64 = value.into();
+    if wide.is_nan() {
+        return write!(f, "NaN");
+    }
+    if wide.is_infinite() {
+        return write!(f, "{}Infinity", if wide < 0.0 { "-" } else { "" });
+    }
+
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("LowerExp always writes an exponent");
+    let exponent: i32 = exponent
+        .parse()
+        .expect("LowerExp writes a decimal exponent");
+    if (-4..max_exponent).contains(&exponent) {
+        write!(f, "{}", value)
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        write!(f, "{}e{}{:02}", mantissa, sign, exponent.abs())
+    }
+}
+
+impl fmt::Display for Datum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Datum::Null => write!(f, "NULL"),
+            Datum::Bool(value) => write!(f, "{}", if *value { "t" } else { "f" }),
+            Datum::Char(value) => write!(f, "{}", *value as u8 as char),
+            Datum::Int2(value) => write!(f, "{}", value),
+            Datum::Int4(value) => write!(f, "{}", value),
+            Datum::Int8(value) => write!(f, "{}", value),
+            Datum::Float4(value) => format_float(f, *value, 6),
+            Datum::Float8(value) => format_float(f, *value, 15),
+            Datum::Oid(value) => write!(f, "{}", value),
+            Datum::Text(value) | Datum::Numeric(value) => write!(f, "{}", value),
+            Datum::Bytea(bytes) | Datum::Raw(bytes) => {
+                write!(f, "\\x")?;
+                bytes.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            }
+            Datum::Date(days) => match *days {
+                i32::MAX => write!(f, "infinity"),
+                i32::MIN => write!(f, "-infinity"),
+                days => {
+                    let bc = format_date(f, days as i64)?;
+                    format_era(f, bc)
+                }
+            },
+            Datum::Time(usecs) => format_time(f, *usecs),
+            Datum::Timestamp(usecs) => format_timestamp(f, *usecs, ""),
+            Datum::TimestampTz(usecs) => format_timestamp(f, *usecs, "+00"),
+            Datum::External(external) => write!(
+                f,
+                "<toast value {} in {}, {} bytes>",
+                external.va_valueid(),
+                external.va_toastrelid(),
+                external.raw_size()
+            ),
+            Datum::Uuid(bytes) => {
+                for (index, byte) in bytes.iter().enumerate() {
+                    if matches!(index, 4 | 6 | 8 | 10) {
+                        write!(f, "-")?;
+                    }
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varlena_1b(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![(((payload.len() + 1) << 1) | 1) as u8];
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn numeric_formats() {
+        // Short format: 623.75 is weight 0, dscale 2, digits [623, 7500].
+        let short = [0x00, 0x81, 0x6f, 0x02, 0x4c, 0x1d];
+        assert_eq!(
+            numeric_to_string(&short, Endianness::LittleEndian).unwrap(),
+            "623.75"
+        );
+
+        // Long format with negative sign: -0.0005, weight -1, dscale 4, digits [5].
+        let long = [0x04, 0x40, 0xff, 0xff, 0x05, 0x00];
+        assert_eq!(
+            numeric_to_string(&long, Endianness::LittleEndian).unwrap(),
+            "-0.0005"
+        );
+
+        let nan = [0x00, 0xc0];
+        assert_eq!(
+            numeric_to_string(&nan, Endianness::LittleEndian).unwrap(),
+            "NaN"
+        );
+    }
+
+    #[test]
+    fn displays_values_like_postgres() {
+        // Expected text is what psql prints with TimeZone set to UTC.
+        let cases = [
+            (Datum::Null, "NULL"),
+            (Datum::Bool(true), "t"),
+            (Datum::Bool(false), "f"),
+            (Datum::Date(8825), "2024-02-29"),
+            (Datum::Date(-746117), "0044-03-15 BC"),
+            (Datum::Date(i32::MAX), "infinity"),
+            (Datum::Date(i32::MIN), "-infinity"),
+            (Datum::Time(0), "00:00:00"),
+            (Datum::Time(45_296_500_000), "12:34:56.5"),
+            (Datum::Time(86_399_999_999), "23:59:59.999999"),
+            (
+                Datum::Timestamp(762_529_501_000_123),
+                "2024-02-29 13:45:01.000123",
+            ),
+            (
+                Datum::Timestamp(-64_464_465_600_000_000),
+                "0044-03-15 12:00:00 BC",
+            ),
+            (Datum::Timestamp(i64::MAX), "infinity"),
+            (Datum::Timestamp(i64::MIN), "-infinity"),
+            (
+                Datum::TimestampTz(762_529_501_250_000),
+                "2024-02-29 13:45:01.25+00",
+            ),
+            (
+                Datum::TimestampTz(-64_464_465_600_000_000),
+                "0044-03-15 12:00:00+00 BC",
+            ),
+            (Datum::TimestampTz(i64::MIN), "-infinity"),
+            (Datum::Float4(f32::NAN), "NaN"),
+            (Datum::Float4(f32::INFINITY), "Infinity"),
+            (Datum::Float4(f32::NEG_INFINITY), "-Infinity"),
+            (Datum::Float4(-0.0), "-0"),
+            (Datum::Float4(1.5), "1.5"),
+            (Datum::Float4(123456.0), "123456"),
+            (Datum::Float4(1234567.0), "1.234567e+06"),
+            (Datum::Float4(1e10), "1e+10"),
+            (Datum::Float4(0.0001), "0.0001"),
+            (Datum::Float4(0.00001), "1e-05"),
+            (Datum::Float4(f32::MAX), "3.4028235e+38"),
+            (Datum::Float8(f64::NAN), "NaN"),
+            (Datum::Float8(f64::INFINITY), "Infinity"),
+            (Datum::Float8(f64::NEG_INFINITY), "-Infinity"),
+            (Datum::Float8(-0.0), "-0"),
+            (Datum::Float8(0.1), "0.1"),
+            (Datum::Float8(1e14), "100000000000000"),
+            (Datum::Float8(1e15), "1e+15"),
+            (Datum::Float8(123456789012345.6), "123456789012345.6"),
+            (Datum::Float8(1.5e-5), "1.5e-05"),
+            (Datum::Float8(f64::MAX), "1.7976931348623157e+308"),
+            (Datum::Float8(5e-324), "5e-324"),
+            (
+                Datum::Uuid([
+                    0xa0, 0xee, 0xbc, 0x99, 0x9c, 0x0b, 0x4e, 0xf8, 0xbb, 0x6d, 0x6b, 0xb9, 0xbd,
+                    0x38, 0x0a, 0x11,
+                ]),
+                "a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11",
+            ),
+            (Datum::Bytea(vec![0xde, 0xad, 0xbe, 0xef]), "\\xdeadbeef"),
+            (Datum::Bytea(Vec::new()), "\\x"),
+        ];
+        for (datum, expected) in cases {
+            assert_eq!(datum.to_string(), expected, "{:?}", datum);
+        }
+    }
+
+    #[test]
+    fn decodes_varlenas_and_nulls() {
+        let descriptor = TupleDescriptor::new(vec![
+            Attribute::new(oid::TEXTOID, -1, false, TypeAlign::Int),
+            Attribute::new(oid::BYTEAOID, -1, false, TypeAlign::Int),
+            Attribute::new(oid::INT4OID, 4, true, TypeAlign::Int),
+            Attribute::new(oid::UUIDOID, 16, false, TypeAlign::Char),
+        ]);
+        // A short text header, then a 4-byte bytea header at the next int boundary.
+        let mut data = varlena_1b("héllo".as_bytes());
+        data.resize(8, 0);
+        data.extend_from_slice(&(8u32 << 2).to_le_bytes());
+        data.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        data.extend(1..=16u8);
+        // The int4 column is null.
+        let null_bitmap = [0b1011];
+
+        let values = decode_tuple(
+            &descriptor,
+            &data,
+            Some(&null_bitmap),
+            4,
+            Endianness::LittleEndian,
+        )
+        .unwrap();
+        let text = values.iter().map(Datum::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            text,
+            [
+                "héllo",
+                "\\xdeadbeef",
+                "NULL",
+                "01020304-0506-0708-090a-0b0c0d0e0f10"
+            ]
+        );
+    }
+
+    #[test]
+    fn aligns_fixed_width_but_not_short_varlenas() {
+        let descriptor = TupleDescriptor::new(vec![
+            Attribute::new(oid::BOOLOID, 1, true, TypeAlign::Char),
+            Attribute::new(oid::TEXTOID, -1, false, TypeAlign::Int),
+            Attribute::new(oid::INT8OID, 8, true, TypeAlign::Double),
+        ]);
+        let mut data = vec![1u8];
+        data.extend(varlena_1b(b"abc"));
+        data.resize(8, 0);
+        data.extend_from_slice(&42i64.to_le_bytes());
+
+        let values = decode_tuple(&descriptor, &data, None, 3, Endianness::LittleEndian).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                Datum::Bool(true),
+                Datum::Text("abc".to_string()),
+                Datum::Int8(42)
+            ]
+        );
+    }
+}
@@ -0,0 +1,446 @@
+//! Free space maps, the `_fsm` fork of a relation (`freespace.c`, `fsmpage.c`).
+//!
+//! Each FSM page holds a binary tree of one-byte nodes after the page header:
+//! the leaves give the free space category of one heap block each, and every
+//! other node the maximum of its children. The pages form a tree in turn, three
+//! levels deep for the default block size: a leaf of a higher-level page holds
+//! the root value of a page on the level below. Categories split the block into
+//! 256 steps, with the top one meaning room for a tuple of any size.
+
+use crate::relation::RelationReader;
+use crate::{
+    is_valid_block_size, read_page_header, read_u32, BlockNumber, Endianness, SIZE_OF_ITEM_ID_DATA,
+    SIZE_OF_PAGE_HEADER_DATA,
+};
+use std::fmt;
+use std::io::{self, Read, Seek};
+
+/// Number of free space categories (`FSM_CATEGORIES`).
+const FSM_CATEGORIES: usize = 256;
+/// Size of `fp_next_slot`, ahead of the nodes in `FSMPageData`.
+const SIZE_OF_NEXT_SLOT: usize = 4;
+/// Fewest leaves per page for which three levels address every block
+/// (`FSM_TREE_DEPTH`).
+const MIN_SLOTS_FOR_THREE_LEVELS: usize = 1626;
+
+/// Node counts of FSM pages for a block size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsmLayout {
+    block_size: usize,
+}
+
+impl FsmLayout {
+    pub fn new(block_size: usize) -> FsmLayout {
+        FsmLayout { block_size }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Nodes in a page (`NodesPerPage`).
+    pub fn nodes_per_page(&self) -> usize {
+        self.block_size - SIZE_OF_PAGE_HEADER_DATA - SIZE_OF_NEXT_SLOT
+    }
+
+    /// Nodes above the leaves (`NonLeafNodesPerPage`).
+    pub fn non_leaf_nodes_per_page(&self) -> usize {
+        self.block_size / 2 - 1
+    }
+
+    /// Leaves, and so heap blocks or lower pages, per page (`SlotsPerFSMPage`).
+    pub fn slots_per_page(&self) -> usize {
+        self.nodes_per_page() - self.non_leaf_nodes_per_page()
+    }
+
+    /// Levels of pages (`FSM_TREE_DEPTH`).
+    pub fn tree_depth(&self) -> u32 {
+        if self.slots_per_page() >= MIN_SLOTS_FOR_THREE_LEVELS {
+            3
+        } else {
+            4
+        }
+    }
+
+    /// Bytes one category step stands for (`FSM_CAT_STEP`).
+    fn category_step(&self) -> usize {
+        self.block_size / FSM_CATEGORIES
+    }
+
+    /// Largest tuple a heap page takes, the space the top category stands for
+    /// (`MaxFSMRequestSize`).
+    fn max_request_size(&self) -> usize {
+        self.block_size - (SIZE_OF_PAGE_HEADER_DATA + SIZE_OF_ITEM_ID_DATA).next_multiple_of(8)
+    }
+
+    /// Bytes of free space at least available in a block of `category`
+    /// (`fsm_space_cat_to_avail`).
+    pub fn category_to_avail(&self, category: u8) -> usize {
+        match category {
+            u8::MAX => self.max_request_size(),
+            category => category as usize * self.category_step(),
+        }
+    }
+
+    /// The category recorded for a block with `avail` bytes free
+    /// (`fsm_space_avail_to_cat`).
+    pub fn avail_to_category(&self, avail: usize) -> u8 {
+        if avail >= self.max_request_size() {
+            return u8::MAX;
+        }
+        (avail / self.category_step()).min(u8::MAX as usize - 1) as u8
+    }
+
+    /// The block of the page at `level`, counted up from the bottom, holding
+    /// entry `page_number` of that level (`fsm_logical_to_physical`).
+    pub fn logical_to_physical(&self, level: u32, page_number: u32) -> BlockNumber {
+        let slots = self.slots_per_page() as u64;
+        let mut leaf_number = page_number as u64;
+        for _ in 0..level {
+            leaf_number *= slots;
+        }
+        // Count the pages that precede this one, on every level.
+        let mut pages = 0;
+        for _ in 0..self.tree_depth() {
+            pages += leaf_number + 1;
+            leaf_number /= slots;
+        }
+        BlockNumber((pages - level as u64 - 1) as u32)
+    }
+
+    /// The bottom-level page and leaf recording `heap_block` (`fsm_get_location`).
+    pub fn location(&self, heap_block: BlockNumber) -> (BlockNumber, usize) {
+        let slots = self.slots_per_page() as u32;
+        (
+            self.logical_to_physical(0, *heap_block / slots),
+            (*heap_block % slots) as usize,
+        )
+    }
+}
+
+/// One page of a free space map (`FSMPageData`).
+#[derive(Debug, Clone)]
+pub struct FsmPage {
+    /// Leaf to start the next search from.
+    fp_next_slot: i32,
+    nodes: Vec<u8>,
+    layout: FsmLayout,
+}
+
+impl FsmPage {
+    /// Decodes the tree of a page; an all-zero page is an empty tree, as the
+    /// server treats it.
+    ///
+    /// The page must be a whole block, of a size PostgreSQL can be built with.
+    pub fn from_bytes(page: &[u8], endianness: Endianness) -> io::Result<FsmPage> {
+        // Checked before the layout, whose node counts underflow for tiny pages.
+        if !is_valid_block_size(page.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("FSM page of {} bytes is not a valid block size", page.len()),
+            ));
+        }
+        let layout = FsmLayout::new(page.len());
+        let mut contents = &page[SIZE_OF_PAGE_HEADER_DATA..];
+        let fp_next_slot = read_u32(&mut contents, endianness)? as i32;
+        Ok(FsmPage {
+            fp_next_slot,
+            nodes: contents.to_vec(),
+            layout,
+        })
+    }
+
+    pub fn fp_next_slot(&self) -> i32 {
+        self.fp_next_slot
+    }
+
+    /// Every node, breadth first from the root.
+    pub fn nodes(&self) -> &[u8] {
+        &self.nodes
+    }
+
+    /// The highest category on the page (`fsm_get_max_avail`).
+    pub fn root(&self) -> u8 {
+        self.nodes[0]
+    }
+
+    /// The leaf categories, one per slot.
+    pub fn leaves(&self) -> &[u8] {
+        &self.nodes[self.layout.non_leaf_nodes_per_page()..]
+    }
+
+    /// Inner nodes that are not the maximum of their children, as left by a
+    /// crash between updating a leaf and its parents; the server repairs them
+    /// as it searches.
+    pub fn inconsistent_nodes(&self) -> Vec<usize> {
+        (0..self.layout.non_leaf_nodes_per_page())
+            .filter(|&node| {
+                let children = [2 * node + 1, 2 * node + 2]
+                    .iter()
+                    .filter_map(|&child| self.nodes.get(child).copied())
+                    .max()
+                    .unwrap_or(0);
+                self.nodes[node] != children
+            })
+            .collect()
+    }
+}
+
+/// Reads the free space recorded for heap blocks from an `_fsm` fork.
+pub struct FsmReader<R> {
+    relation: RelationReader<R>,
+    layout: FsmLayout,
+    /// The page last read, since consecutive heap blocks share one.
+    page: Option<(BlockNumber, FsmPage)>,
+}
+
+impl<R: Read + Seek> FsmReader<R> {
+    pub fn new(relation: RelationReader<R>) -> FsmReader<R> {
+        FsmReader {
+            layout: FsmLayout::new(relation.block_size()),
+            relation,
+            page: None,
+        }
+    }
+
+    pub fn layout(&self) -> FsmLayout {
+        self.layout
+    }
+
+    /// Number of pages in the fork.
+    pub fn n_blocks(&self) -> u32 {
+        self.relation.n_blocks()
+    }
+
+    /// Reads the page at `block`, or `None` past the end of the fork.
+    pub fn page(&mut self, block: BlockNumber) -> io::Result<Option<&FsmPage>> {
+        if *block >= self.relation.n_blocks() {
+            return Ok(None);
+        }
+        if !matches!(&self.page, Some((cached, _)) if *cached == block) {
+            let mut buffer = vec![0u8; self.layout.block_size()];
+            self.relation.read_block_bytes(block, &mut buffer)?;
+            let page = FsmPage::from_bytes(&buffer, self.relation.endianness())?;
+            self.page = Some((block, page));
+        }
+        Ok(self.page.as_ref().map(|(_, page)| page))
+    }
+
+    /// The category recorded for `heap_block`, zero if the map does not reach
+    /// it (`GetRecordedFreeSpace`).
+    pub fn category(&mut self, heap_block: BlockNumber) -> io::Result<u8> {
+        let (block, slot) = self.layout.location(heap_block);
+        Ok(self.page(block)?.map_or(0, |page| page.leaves()[slot]))
+    }
+
+    /// The bytes of free space recorded for `heap_block`.
+    pub fn free_space(&mut self, heap_block: BlockNumber) -> io::Result<usize> {
+        let category = self.category(heap_block)?;
+        Ok(self.layout.category_to_avail(category))
+    }
+}
+
+/// How the free space recorded for a heap block compares with the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeSpaceStatus {
+    Consistent,
+    /// The page has more room than recorded, as until the next vacuum.
+    Understated,
+    /// The map promises more room than the page has.
+    Overstated,
+}
+
+impl fmt::Display for FreeSpaceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FreeSpaceStatus::Consistent => "ok",
+            FreeSpaceStatus::Understated => "understated",
+            FreeSpaceStatus::Overstated => "overstated",
+        })
+    }
+}
+
+/// Free space of one heap block, as recorded in the map and as found on the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockFreeSpace {
+    block: BlockNumber,
+    category: u8,
+    recorded: usize,
+    /// `pd_upper - pd_lower`, or all but the header for a new page.
+    actual: usize,
+    status: FreeSpaceStatus,
+}
+
+impl BlockFreeSpace {
+    pub fn block(&self) -> BlockNumber {
+        self.block
+    }
+
+    pub fn category(&self) -> u8 {
+        self.category
+    }
+
+    pub fn recorded(&self) -> usize {
+        self.recorded
+    }
+
+    pub fn actual(&self) -> usize {
+        self.actual
+    }
+
+    pub fn status(&self) -> FreeSpaceStatus {
+        self.status
+    }
+}
+
+/// Compares the free space `fsm` records for each block of `heap` with the
+/// room left on the page.
+///
+/// Vacuum records the space left after one more line pointer
+/// (`PageGetHeapFreeSpace`), so a block counts as understated only when that
+/// would fall in a higher category than recorded.
+pub fn compare_free_space<H: Read + Seek, R: Read + Seek>(
+    heap: &mut RelationReader<H>,
+    fsm: &mut FsmReader<R>,
+) -> io::Result<Vec<BlockFreeSpace>> {
+    let layout = fsm.layout();
+    if heap.block_size() != layout.block_size() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Heap has {}-byte blocks but its free space map {}-byte ones",
+                heap.block_size(),
+                layout.block_size()
+            ),
+        ));
+    }
+    let mut buffer = vec![0u8; heap.block_size()];
+    let mut blocks = Vec::with_capacity(heap.n_blocks() as usize);
+    for block in (0..heap.n_blocks()).map(BlockNumber) {
+        heap.read_block_bytes(block, &mut buffer)?;
+        let header = read_page_header(&mut &buffer[..], heap.endianness())?;
+        // Vacuum records a new page as empty (`lazy_scan_new_or_empty`).
+        let (actual, usable) = if header.is_new() {
+            let empty = heap.block_size() - SIZE_OF_PAGE_HEADER_DATA;
+            (empty, empty)
+        } else {
+            let actual = header.pd_upper().saturating_sub(**header.pd_lower()) as usize;
+            (actual, actual.saturating_sub(SIZE_OF_ITEM_ID_DATA))
+        };
+        let category = fsm.category(block)?;
+        let recorded = layout.category_to_avail(category);
+        let status = if recorded > actual {
+            FreeSpaceStatus::Overstated
+        } else if layout.avail_to_category(usable) > category {
+            FreeSpaceStatus::Understated
+        } else {
+            FreeSpaceStatus::Consistent
+        };
+        blocks.push(BlockFreeSpace {
+            block,
+            category,
+            recorded,
+            actual,
+            status,
+        });
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn locates_heap_blocks() {
+        let layout = FsmLayout::new(8192);
+        assert_eq!(layout.slots_per_page(), 4069);
+        assert_eq!(layout.tree_depth(), 3);
+        assert_eq!(FsmLayout::new(1024).tree_depth(), 4);
+        // Root, first middle page, then the bottom pages under it.
+        assert_eq!(layout.location(BlockNumber(5)), (BlockNumber(2), 5));
+        assert_eq!(layout.location(BlockNumber(4069)), (BlockNumber(3), 0));
+        assert_eq!(layout.logical_to_physical(1, 1), BlockNumber(4071));
+        assert_eq!(layout.logical_to_physical(2, 0), BlockNumber(0));
+
+        assert_eq!(layout.category_to_avail(255), 8160);
+        assert_eq!(layout.category_to_avail(10), 320);
+        assert_eq!(layout.avail_to_category(8168), 255);
+        assert_eq!(layout.avail_to_category(8159), 254);
+        assert_eq!(layout.avail_to_category(351), 10);
+
+        let mut page = vec![0u8; 8192];
+        let leaves = SIZE_OF_PAGE_HEADER_DATA + SIZE_OF_NEXT_SLOT + 4095;
+        page[leaves + 5] = 10;
+        let page = FsmPage::from_bytes(&page, Endianness::LittleEndian).unwrap();
+        assert_eq!(page.leaves().len(), 4069);
+        assert_eq!(page.leaves()[5], 10);
+        // The parent of leaf 5 was never raised to 10.
+        assert_eq!(page.inconsistent_nodes(), vec![(4095 + 5 - 1) / 2]);
+    }
+
+    /// A heap page with `pd_upper - pd_lower` bytes free, or a new page for zero.
+    fn heap_page(pd_lower: u16, pd_upper: u16) -> Vec<u8> {
+        let mut page = vec![0u8; 8192];
+        if pd_upper != 0 {
+            page[12..14].copy_from_slice(&pd_lower.to_le_bytes());
+            page[14..16].copy_from_slice(&pd_upper.to_le_bytes());
+            page[16..18].copy_from_slice(&8192u16.to_le_bytes());
+            page[18..20].copy_from_slice(&(8192u16 | 4).to_le_bytes());
+        }
+        page
+    }
+
+    fn reader(pages: Vec<Vec<u8>>) -> RelationReader<Cursor<Vec<u8>>> {
+        let data = pages.concat();
+        RelationReader::new(Cursor::new(data), Some(8192), Endianness::LittleEndian).unwrap()
+    }
+
+    #[test]
+    fn compares_recorded_and_actual_free_space() {
+        let mut heap = reader(vec![
+            heap_page(28, 28 + 3000),
+            heap_page(40, 8000),
+            heap_page(0, 0),
+            heap_page(0, 0),
+            heap_page(40, 40 + 1600),
+        ]);
+        // Root and middle pages, then the bottom page for the first 4069 blocks.
+        let mut bottom = vec![0u8; 8192];
+        let leaves = SIZE_OF_PAGE_HEADER_DATA + SIZE_OF_NEXT_SLOT + 4095;
+        bottom[leaves..leaves + 5].copy_from_slice(&[200, 10, 255, 0, 49]);
+        let mut fsm = FsmReader::new(reader(vec![vec![0; 8192], vec![0; 8192], bottom]));
+
+        let blocks = compare_free_space(&mut heap, &mut fsm).unwrap();
+        let statuses: Vec<_> = blocks.iter().map(BlockFreeSpace::status).collect();
+        assert_eq!(
+            statuses,
+            [
+                FreeSpaceStatus::Overstated,
+                FreeSpaceStatus::Understated,
+                FreeSpaceStatus::Consistent,
+                FreeSpaceStatus::Understated,
+                FreeSpaceStatus::Consistent,
+            ]
+        );
+        assert_eq!(blocks[0].recorded(), 6400);
+        assert_eq!(blocks[0].actual(), 3000);
+        // New pages count as empty.
+        assert_eq!(blocks[2].actual(), 8192 - SIZE_OF_PAGE_HEADER_DATA);
+
+        // Blocks past the end of the map have nothing recorded.
+        assert_eq!(fsm.n_blocks(), 3);
+        assert!(fsm.page(BlockNumber(3)).unwrap().is_none());
+        assert_eq!(fsm.category(BlockNumber(4069)).unwrap(), 0);
+        let mut root_only = FsmReader::new(reader(vec![vec![0; 8192]]));
+        assert_eq!(root_only.category(BlockNumber(0)).unwrap(), 0);
+        let blocks = compare_free_space(&mut heap, &mut root_only).unwrap();
+        assert_eq!(blocks[1].status(), FreeSpaceStatus::Understated);
+
+        // Too small for any block size, without panicking.
+        for len in [0, 1, 28, 1000] {
+            assert!(FsmPage::from_bytes(&vec![0; len], Endianness::LittleEndian).is_err());
+        }
+    }
+}
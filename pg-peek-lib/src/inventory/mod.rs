@@ -0,0 +1,406 @@
+//! The relation files of a data directory (`relpath.c`, `reinit.c`).
+//!
+//! Relation files live in `global`, `base/<database>` and
+//! `pg_tblspc/<tablespace>/<version directory>/<database>`. Each is named after
+//! the relation's filenode, with a suffix for forks other than the main one and
+//! a `.N` extension for segments past the first. Temporary relations carry the
+//! number of the backend owning them as a `t<backend>_` prefix.
+
+use crate::catalog::{RelFileLocator, RelKind, DEFAULT_TABLESPACE_OID, GLOBAL_TABLESPACE_OID};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use strum_macros::{Display, EnumString};
+
+/// A fork of a relation (`ForkNumber`), named as in file suffixes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumString, Display)]
+pub enum ForkNumber {
+    #[strum(serialize = "main")]
+    Main,
+    #[strum(serialize = "fsm")]
+    FreeSpaceMap,
+    #[strum(serialize = "vm")]
+    VisibilityMap,
+    #[strum(serialize = "init")]
+    Init,
+}
+
+/// The parts of a relation file name such as `16384`, `16384_fsm.2` or `t3_16384`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelationFileName {
+    /// Backend owning a temporary relation.
+    backend: Option<u32>,
+    rel_number: u32,
+    fork: ForkNumber,
+    segment: u32,
+}
+
+impl RelationFileName {
+    /// Splits `name`, or returns `None` if it is not a relation file name
+    /// (`parse_filename_for_nontemp_relation`, `looks_like_temp_rel_name`).
+    pub fn parse(name: &str) -> Option<RelationFileName> {
+        let (backend, name) = match name.strip_prefix('t') {
+            Some(rest) => {
+                let (backend, name) = rest.split_once('_')?;
+                (Some(parse_number(backend)?), name)
+            }
+            None => (None, name),
+        };
+        let (stem, segment) = match name.split_once('.') {
+            Some((stem, segment)) => (stem, parse_number(segment)?),
+            None => (name, 0),
+        };
+        let (rel_number, fork) = match stem.split_once('_') {
+            Some((_, "main")) => return None,
+            Some((rel_number, fork)) => (rel_number, fork.parse().ok()?),
+            None => (stem, ForkNumber::Main),
+        };
+        Some(RelationFileName {
+            backend,
+            rel_number: parse_number(rel_number)?,
+            fork,
+            segment,
+        })
+    }
+
+    pub fn backend(&self) -> Option<u32> {
+        self.backend
+    }
+
+    pub fn rel_number(&self) -> u32 {
+        self.rel_number
+    }
+
+    pub fn fork(&self) -> ForkNumber {
+        self.fork
+    }
+
+    pub fn segment(&self) -> u32 {
+        self.segment
+    }
+
+    pub fn is_temp(&self) -> bool {
+        self.backend.is_some()
+    }
+}
+
+/// Parses a run of decimal digits, without the sign `str::parse` accepts.
+fn parse_number(digits: &str) -> Option<u32> {
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// A relation file found in a data directory.
+#[derive(Debug, Clone)]
+pub struct RelationFile {
+    path: PathBuf,
+    size: u64,
+    locator: RelFileLocator,
+    name: RelationFileName,
+}
+
+impl RelationFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Size in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The relation the file belongs to; its database is zero in `global`.
+    pub fn locator(&self) -> RelFileLocator {
+        self.locator
+    }
+
+    pub fn name(&self) -> RelationFileName {
+        self.name
+    }
+}
+
+/// A relation with storage, as `pg_class` describes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredRelation {
+    kind: RelKind,
+    /// `schema.name`.
+    name: String,
+    /// Only a `pg_class` row inserted by a transaction still in progress points here.
+    uncommitted: bool,
+}
+
+impl StoredRelation {
+    pub fn new(kind: RelKind, name: String, uncommitted: bool) -> StoredRelation {
+        StoredRelation {
+            kind,
+            name,
+            uncommitted,
+        }
+    }
+
+    pub fn kind(&self) -> RelKind {
+        self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the relation is being created, truncated or rewritten by a
+    /// transaction still in progress.
+    pub fn is_uncommitted(&self) -> bool {
+        self.uncommitted
+    }
+}
+
+/// What a relation file belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileOwner<'a> {
+    Relation(&'a StoredRelation),
+    /// The catalogs of the file's database could not be read.
+    Unknown,
+    /// No `pg_class` row points to the file.
+    Orphan,
+}
+
+/// Lists the relation files of a data directory with what each belongs to.
+///
+/// `relations` holds the relations with storage of every database by where they
+/// are stored. Files of the databases in `unreadable`, such as one half-dropped,
+/// are left unmatched rather than orphaned.
+pub fn inventory<'a>(
+    data_directory: &Path,
+    version_directory: &str,
+    relations: &'a HashMap<RelFileLocator, StoredRelation>,
+    unreadable: &HashSet<u32>,
+) -> io::Result<Vec<(RelationFile, FileOwner<'a>)>> {
+    let files = relation_files(data_directory, version_directory)?;
+    Ok(files
+        .into_iter()
+        .map(|file| {
+            let owner = match relations.get(&file.locator) {
+                Some(relation) => FileOwner::Relation(relation),
+                None if unreadable.contains(&file.locator.db_oid()) => FileOwner::Unknown,
+                None => FileOwner::Orphan,
+            };
+            (file, owner)
+        })
+        .collect())
+}
+
+/// Lists the relation files of a data directory, by tablespace, database,
+/// filenode, fork and segment.
+///
+/// Only the `version_directory` of each tablespace is read, as other versions
+/// belong to other servers. Files whose names are not relation file names, such
+/// as `PG_VERSION` and `pg_filenode.map`, are left out.
+pub fn relation_files(
+    data_directory: &Path,
+    version_directory: &str,
+) -> io::Result<Vec<RelationFile>> {
+    let mut files = Vec::new();
+    database_files(
+        &data_directory.join("global"),
+        GLOBAL_TABLESPACE_OID,
+        0,
+        &mut files,
+    )?;
+    for (db_oid, database_dir) in numbered_entries(&data_directory.join("base"))? {
+        database_files(&database_dir, DEFAULT_TABLESPACE_OID, db_oid, &mut files)?;
+    }
+    for (spc_oid, tablespace_dir) in numbered_entries(&data_directory.join("pg_tblspc"))? {
+        for (db_oid, database_dir) in numbered_entries(&tablespace_dir.join(version_directory))? {
+            database_files(&database_dir, spc_oid, db_oid, &mut files)?;
+        }
+    }
+    Ok(files)
+}
+
+/// The entries of `directory` named by a number, in numeric order; none if it
+/// does not exist.
+fn numbered_entries(directory: &Path) -> io::Result<Vec<(u32, PathBuf)>> {
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        if let Some(number) = entry.file_name().to_str().and_then(parse_number) {
+            entries.push((number, entry.path()));
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+fn database_files(
+    directory: &Path,
+    spc_oid: u32,
+    db_oid: u32,
+    files: &mut Vec<RelationFile>,
+) -> io::Result<()> {
+    if !directory.is_dir() {
+        return Ok(());
+    }
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().and_then(RelationFileName::parse) else {
+            continue;
+        };
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        found.push(RelationFile {
+            path: entry.path(),
+            size: metadata.len(),
+            locator: RelFileLocator::new(spc_oid, db_oid, name.rel_number),
+            name,
+        });
+    }
+    found.sort_by_key(|file| {
+        (
+            file.name.rel_number,
+            file.name.backend,
+            file.name.fork,
+            file.name.segment,
+        )
+    });
+    files.append(&mut found);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_file_names() {
+        let parsed = |backend, rel_number, fork, segment| {
+            Some(RelationFileName {
+                backend,
+                rel_number,
+                fork,
+                segment,
+            })
+        };
+        assert_eq!(
+            RelationFileName::parse("16384_fsm.2"),
+            parsed(None, 16384, ForkNumber::FreeSpaceMap, 2)
+        );
+        assert_eq!(
+            RelationFileName::parse("t3_16390"),
+            parsed(Some(3), 16390, ForkNumber::Main, 0)
+        );
+        assert_eq!(
+            RelationFileName::parse("16384_init"),
+            parsed(None, 16384, ForkNumber::Init, 0)
+        );
+        for name in [
+            "PG_VERSION",
+            "pg_filenode.map",
+            "16384_main",
+            "16384.+1",
+            "t_16384",
+        ] {
+            assert_eq!(RelationFileName::parse(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn matches_files_to_relations() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path();
+        let files = [
+            "global/1262",
+            "global/pg_filenode.map",
+            "base/16384/PG_VERSION",
+            "base/16384/16500",
+            "base/16384/16500.1",
+            "base/16384/16500_fsm",
+            "base/16384/t3_16510",
+            "base/16384/16520",
+            "base/16384/16530",
+            "base/16390/16500",
+            "pg_tblspc/16395/PG_15_202209061/16384/16540",
+            // Left behind by another major version sharing the tablespace.
+            "pg_tblspc/16395/PG_14_202107181/16384/16550",
+        ];
+        for file in files {
+            let path = data.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, [0u8; 8]).unwrap();
+        }
+
+        let stored = |kind, name: &str, uncommitted| {
+            StoredRelation::new(kind, name.to_string(), uncommitted)
+        };
+        let relations = HashMap::from([
+            (
+                RelFileLocator::new(GLOBAL_TABLESPACE_OID, 0, 1262),
+                stored(RelKind::Table, "pg_catalog.pg_database", false),
+            ),
+            (
+                RelFileLocator::new(DEFAULT_TABLESPACE_OID, 16384, 16500),
+                stored(RelKind::Table, "public.orders", false),
+            ),
+            (
+                RelFileLocator::new(DEFAULT_TABLESPACE_OID, 16384, 16510),
+                stored(RelKind::Table, "pg_temp_3.scratch", false),
+            ),
+            (
+                RelFileLocator::new(DEFAULT_TABLESPACE_OID, 16384, 16530),
+                stored(RelKind::Table, "public.staging", true),
+            ),
+            (
+                RelFileLocator::new(16395, 16384, 16540),
+                stored(RelKind::Index, "public.orders_pkey", false),
+            ),
+        ]);
+        // The catalogs of database 16390 could not be read.
+        let unreadable = HashSet::from([16390]);
+
+        let listed = inventory(data, "PG_15_202209061", &relations, &unreadable).unwrap();
+        let owners: Vec<_> = listed
+            .iter()
+            .map(|(file, owner)| {
+                let path = file.path().strip_prefix(data).unwrap();
+                let owner = match owner {
+                    FileOwner::Relation(relation) if relation.is_uncommitted() => {
+                        format!("uncommitted {}", relation.name())
+                    }
+                    FileOwner::Relation(relation) => relation.name().to_string(),
+                    FileOwner::Unknown => "?".to_string(),
+                    FileOwner::Orphan => "orphan".to_string(),
+                };
+                (path.to_str().unwrap().to_string(), owner)
+            })
+            .collect();
+        let expected = [
+            ("global/1262", "pg_catalog.pg_database"),
+            ("base/16384/16500", "public.orders"),
+            ("base/16384/16500.1", "public.orders"),
+            ("base/16384/16500_fsm", "public.orders"),
+            ("base/16384/t3_16510", "pg_temp_3.scratch"),
+            ("base/16384/16520", "orphan"),
+            ("base/16384/16530", "uncommitted public.staging"),
+            ("base/16390/16500", "?"),
+            (
+                "pg_tblspc/16395/PG_15_202209061/16384/16540",
+                "public.orders_pkey",
+            ),
+        ]
+        .map(|(path, owner)| (path.to_string(), owner.to_string()));
+        assert_eq!(owners, expected);
+
+        let segment = &listed[2].0;
+        assert_eq!(segment.name().fork(), ForkNumber::Main);
+        assert_eq!(segment.name().segment(), 1);
+        assert_eq!(listed[3].0.name().fork(), ForkNumber::FreeSpaceMap);
+        assert_eq!(listed[4].0.name().backend(), Some(3));
+        assert_eq!(segment.size(), 8);
+    }
+}
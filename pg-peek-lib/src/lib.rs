@@ -1,12 +1,36 @@
+pub mod catalog;
+pub mod checksum;
+pub mod clog;
+pub mod control;
+pub mod crc;
+pub mod datum;
+pub mod fsm;
+pub mod inventory;
+pub mod multixact;
+pub mod pglz;
+pub mod relation;
+pub mod relmap;
+pub mod slru;
+pub mod subtrans;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod toast;
 pub mod types;
+pub mod varlena;
+pub mod view;
+pub mod visibility;
+
+use checksum::ChecksumStatus;
+use relation::RelationReader;
+pub use varlena::Varlena;
 
-use bitflags::Flags;
 use byteorder::ReadBytesExt;
-use std::io::{self, Read};
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
 use std::ops::Deref;
 
 /// Enum representing the possible byte order (endianness) of a system.
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Endianness {
     LittleEndian,
     BigEndian,
@@ -23,21 +47,21 @@ pub fn get_system_endianness() -> Endianness {
     }
 }
 
-fn read_u16<R: Read>(reader: &mut R, endianness: Endianness) -> io::Result<u16> {
+pub(crate) fn read_u16<R: Read>(reader: &mut R, endianness: Endianness) -> io::Result<u16> {
     match endianness {
         Endianness::LittleEndian => reader.read_u16::<byteorder::LittleEndian>(),
         Endianness::BigEndian => reader.read_u16::<byteorder::BigEndian>(),
     }
 }
 
-fn read_u32<R: Read>(reader: &mut R, endianness: Endianness) -> io::Result<u32> {
+pub(crate) fn read_u32<R: Read>(reader: &mut R, endianness: Endianness) -> io::Result<u32> {
     match endianness {
         Endianness::LittleEndian => reader.read_u32::<byteorder::LittleEndian>(),
         Endianness::BigEndian => reader.read_u32::<byteorder::BigEndian>(),
     }
 }
 
-fn read_u64<R: Read>(reader: &mut R, endianness: Endianness) -> io::Result<u64> {
+pub(crate) fn read_u64<R: Read>(reader: &mut R, endianness: Endianness) -> io::Result<u64> {
     match endianness {
         Endianness::LittleEndian => reader.read_u64::<byteorder::LittleEndian>(),
         Endianness::BigEndian => reader.read_u64::<byteorder::BigEndian>(),
@@ -45,12 +69,22 @@ fn read_u64<R: Read>(reader: &mut R, endianness: Endianness) -> io::Result<u64>
 }
 
 /// Represents a pointer to a location in the PostgreSQL write-ahead log.
-#[derive(Debug)]
-pub struct PageXLogRecPtr(u64);
+///
+/// On a page it is stored as two 32-bit halves, `xlogid` then `xrecoff`, so its
+/// byte layout does not depend on the alignment of 64-bit integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PageXLogRecPtr(pub u64);
+
+impl fmt::Display for PageXLogRecPtr {
+    /// Formats the LSN as PostgreSQL does, e.g. `0/16B3748`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 as u32)
+    }
+}
 
 /// Represents a unique identifier for a PostgreSQL transaction.
-#[derive(Debug)]
-pub struct TransactionId(u32);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransactionId(pub u32);
 
 /// Represents a command identifier within a PostgreSQL transaction.
 #[derive(Debug)]
@@ -64,6 +98,10 @@ pub struct LocationIndex(u16);
 #[derive(Debug)]
 pub struct ItemPointerData([u8; 6]);
 
+/// Represents the number of a block within a relation, counted across all segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BlockNumber(pub u32);
+
 impl Deref for PageXLogRecPtr {
     type Target = u64;
 
@@ -72,6 +110,32 @@ impl Deref for PageXLogRecPtr {
     }
 }
 
+impl TransactionId {
+    pub const INVALID: TransactionId = TransactionId(0);
+    pub const BOOTSTRAP: TransactionId = TransactionId(1);
+    pub const FROZEN: TransactionId = TransactionId(2);
+    pub const FIRST_NORMAL: TransactionId = TransactionId(3);
+
+    /// Whether this is an ordinary xid rather than one of the permanent ones below 3.
+    pub fn is_normal(&self) -> bool {
+        self.0 >= Self::FIRST_NORMAL.0
+    }
+
+    /// Whether this xid is older than `other`, modulo 2^32 (`TransactionIdPrecedes`).
+    pub fn precedes(&self, other: TransactionId) -> bool {
+        if !self.is_normal() || !other.is_normal() {
+            return self.0 < other.0;
+        }
+        (self.0.wrapping_sub(other.0) as i32) < 0
+    }
+}
+
+impl fmt::Display for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl Deref for TransactionId {
     type Target = u32;
 
@@ -96,6 +160,14 @@ impl Deref for LocationIndex {
     }
 }
 
+impl Deref for BlockNumber {
+    type Target = u32;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl Deref for ItemPointerData {
     type Target = [u8; 6];
 
@@ -135,9 +207,113 @@ pub struct PageHeaderData {
     pd_prune_xid: TransactionId,
 }
 
+impl PageHeaderData {
+    pub fn pd_lsn(&self) -> &PageXLogRecPtr {
+        &self.pd_lsn
+    }
+
+    pub fn pd_checksum(&self) -> u16 {
+        self.pd_checksum
+    }
+
+    pub fn pd_flags(&self) -> &PageFlags {
+        &self.pd_flags
+    }
+
+    pub fn pd_lower(&self) -> &LocationIndex {
+        &self.pd_lower
+    }
+
+    pub fn pd_upper(&self) -> &LocationIndex {
+        &self.pd_upper
+    }
+
+    pub fn pd_special(&self) -> &LocationIndex {
+        &self.pd_special
+    }
+
+    pub fn pd_pagesize_version(&self) -> u16 {
+        self.pd_pagesize_version
+    }
+
+    pub fn pd_prune_xid(&self) -> &TransactionId {
+        &self.pd_prune_xid
+    }
+
+    /// Page size in bytes, taken from the high byte of `pd_pagesize_version`.
+    pub fn page_size(&self) -> usize {
+        (self.pd_pagesize_version & 0xFF00) as usize
+    }
+
+    /// Page layout version, taken from the low byte of `pd_pagesize_version`.
+    pub fn layout_version(&self) -> u8 {
+        (self.pd_pagesize_version & 0x00FF) as u8
+    }
+
+    /// A page that has been allocated but never initialized is all zeroes.
+    pub fn is_new(&self) -> bool {
+        *self.pd_upper == 0
+    }
+
+    /// Number of line pointers between the header and `pd_lower`.
+    ///
+    /// Fails if the page bounds are inconsistent or run past `page_len`; a new page has none.
+    pub fn item_count(&self, page_len: usize) -> io::Result<usize> {
+        if self.is_new() {
+            return Ok(0);
+        }
+
+        let pd_lower = *self.pd_lower as usize;
+        let pd_upper = *self.pd_upper as usize;
+        let pd_special = *self.pd_special as usize;
+        if pd_lower < SIZE_OF_PAGE_HEADER_DATA {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("pd_lower {} is inside the page header", pd_lower),
+            ));
+        }
+        if pd_lower > pd_upper || pd_upper > pd_special {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Page bounds out of order: pd_lower {}, pd_upper {}, pd_special {}",
+                    pd_lower, pd_upper, pd_special
+                ),
+            ));
+        }
+        if pd_special > page_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "pd_special {} is past the end of the {} byte page",
+                    pd_special, page_len
+                ),
+            ));
+        }
+
+        Ok((pd_lower - SIZE_OF_PAGE_HEADER_DATA) / SIZE_OF_ITEM_ID_DATA)
+    }
+
+    /// Checks that an initialized page records the block size it was read with.
+    pub fn check_page_size(&self, page_len: usize, block_number: BlockNumber) -> io::Result<()> {
+        if !self.is_new() && self.page_size() != page_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Block {} has page size {} but the relation uses {}",
+                    *block_number,
+                    self.page_size(),
+                    page_len
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
 bitflags! {
     /// Flags representing the status and type of a PostgreSQL item.
-    #[derive(Debug, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
     pub struct LPFlags: u8 {
         const LP_UNUSED   = 0x00;
         const LP_NORMAL   = 0x01;
@@ -147,7 +323,7 @@ bitflags! {
 }
 
 /// Represents the data for an item on a PostgreSQL page.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct ItemIdData {
     /// Offset to the item's data.
     lp_off: u16,
@@ -158,32 +334,45 @@ pub struct ItemIdData {
 }
 
 impl ItemIdData {
+    pub fn lp_off(&self) -> u16 {
+        self.lp_off
+    }
+
+    pub fn lp_flags(&self) -> &LPFlags {
+        &self.lp_flags
+    }
+
+    pub fn lp_len(&self) -> u16 {
+        self.lp_len
+    }
+
     pub fn from_reader<R: Read>(reader: &mut R, endianness: Endianness) -> io::Result<Self> {
-        let first_part = read_u16(reader, endianness)?;
-        let second_part = read_u16(reader, endianness)?;
-
-        let lp_off = first_part & 0x7FFF; // Get first 15 bits
-        let raw_flags = ((first_part >> 15) & 0x03) as u8; // Get next 2 bits
-        let lp_flags = LPFlags::from_bits_truncate(raw_flags);
-        let lp_len = second_part & 0x7FFF; // Get 15 bits
-
-        Ok(ItemIdData {
-            lp_off,
-            lp_flags,
-            lp_len,
-        })
+        let word = read_u32(reader, endianness)?;
+        Ok(ItemIdData::from_word(word, endianness))
     }
-}
 
-// Varlena structure
-#[derive(Debug)]
-pub struct Varlena {
-    length: u32,
-    data: Vec<u8>,
+    /// Unpacks the `lp_off:15, lp_flags:2, lp_len:15` bitfield from a line pointer word.
+    ///
+    /// C compilers allocate bitfields from the least significant bit on little-endian
+    /// targets and from the most significant bit on big-endian ones, so the field
+    /// order within the word is reversed between the two.
+    pub fn from_word(word: u32, endianness: Endianness) -> Self {
+        let (lp_off, raw_flags, lp_len) = match endianness {
+            Endianness::LittleEndian => (word & 0x7FFF, (word >> 15) & 0x03, (word >> 17) & 0x7FFF),
+            Endianness::BigEndian => ((word >> 17) & 0x7FFF, (word >> 15) & 0x03, word & 0x7FFF),
+        };
+
+        ItemIdData {
+            lp_off: lp_off as u16,
+            lp_flags: LPFlags::from_bits_truncate(raw_flags as u8),
+            lp_len: lp_len as u16,
+        }
+    }
 }
 
 // BTreeIndex structure
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct BTreeIndex {
     left_sibling: Option<u32>,
     right_sibling: Option<u32>,
@@ -196,6 +385,12 @@ pub struct SpecialSection {
     data: Vec<u8>,
 }
 
+impl SpecialSection {
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
 // TableRow structure
 #[derive(Debug)]
 pub struct TableRow {
@@ -205,54 +400,180 @@ pub struct TableRow {
     user_data: Vec<u8>,
 }
 
+impl From<HeapTuple> for TableRow {
+    fn from(tuple: HeapTuple) -> Self {
+        TableRow {
+            header: tuple.header,
+            null_bitmap: tuple.null_bitmap,
+            oid: tuple.oid,
+            user_data: tuple.data,
+        }
+    }
+}
+
+impl TableRow {
+    pub fn header(&self) -> &HeapTupleHeaderData {
+        &self.header
+    }
+
+    pub fn null_bitmap(&self) -> Option<&[u8]> {
+        self.null_bitmap.as_deref()
+    }
+
+    pub fn oid(&self) -> Option<u32> {
+        self.oid
+    }
+
+    pub fn user_data(&self) -> &[u8] {
+        &self.user_data
+    }
+
+    /// Whether the attribute at zero-based `index` is null.
+    pub fn is_null(&self, index: usize) -> bool {
+        att_isnull(self.null_bitmap(), self.header.natts(), index)
+    }
+}
+
+/// A line pointer resolved against the page it belongs to.
+#[derive(Debug)]
+pub enum PageItem {
+    /// The line pointer is free and has no storage.
+    Unused,
+    /// The line pointer points at a tuple stored at `lp_off`.
+    Normal(HeapTuple),
+    /// HOT redirect; holds the offset number of the line pointer it forwards to.
+    Redirect(u16),
+    /// The tuple is dead; any remaining storage is not decoded.
+    Dead,
+}
+
 // PageLayout structure
 #[derive(Debug)]
 pub struct PageLayout {
+    block_number: BlockNumber,
+    checksum: ChecksumStatus,
     header: PageHeaderData,
     item_identifiers: Vec<ItemIdData>,
-    items: Vec<HeapTuple>,
+    items: Vec<PageItem>,
     special_space: Option<SpecialSection>,
 }
 
 impl PageLayout {
-    pub fn from_reader<R: Read>(reader: &mut R, endianness: Endianness) -> io::Result<PageLayout> {
-        let header = read_page_header(reader, endianness)?;
-        let item_identifiers = read_item_identifiers(reader, &header, endianness)?;
+    /// Reads one full page of `block_size` bytes from `reader` and decodes it.
+    pub fn from_reader<R: Read>(
+        reader: &mut R,
+        block_size: usize,
+        block_number: BlockNumber,
+        endianness: Endianness,
+    ) -> io::Result<PageLayout> {
+        let mut page = vec![0u8; block_size];
+        reader.read_exact(&mut page)?;
+        PageLayout::from_bytes(&page, block_number, endianness)
+    }
+
+    /// Decodes a page held in memory, resolving each line pointer at its `lp_off`.
+    ///
+    /// `block_number` is the page's position in its relation and feeds the checksum.
+    pub fn from_bytes(
+        page: &[u8],
+        block_number: BlockNumber,
+        endianness: Endianness,
+    ) -> io::Result<PageLayout> {
+        let checksum = checksum::verify_page_checksum(page, block_number, endianness);
+        let mut cursor = io::Cursor::new(page);
+        let header = read_page_header(&mut cursor, endianness)?;
+        header.check_page_size(page.len(), block_number)?;
+        let item_identifiers = read_item_identifiers(&mut cursor, &header, endianness)?;
 
-        // Read HeapTuples for each item identifier
-        let mut items = Vec::new();
+        let mut items = Vec::with_capacity(item_identifiers.len());
         for item_id in &item_identifiers {
-            if item_id.lp_flags == LPFlags::LP_NORMAL {
-                let tuple_length = item_id.lp_len as u32; // Assuming lp_len is the length including the header
-                let tuple = HeapTuple::from_reader(reader, tuple_length, endianness)?;
-                items.push(tuple);
-            } else {
-                // Skip non-NORMAL items based on their length
-                let mut buffer = vec![0u8; item_id.lp_len as usize];
-                reader.read_exact(&mut buffer)?;
-            }
+            let item = match item_id.lp_flags {
+                LPFlags::LP_NORMAL => {
+                    let data = item_data(page, item_id)?;
+                    let tuple = HeapTuple::from_reader(
+                        &mut io::Cursor::new(data),
+                        item_id.lp_len as u32,
+                        endianness,
+                    )?;
+                    PageItem::Normal(tuple)
+                }
+                LPFlags::LP_REDIRECT => PageItem::Redirect(item_id.lp_off),
+                LPFlags::LP_DEAD => PageItem::Dead,
+                _ => PageItem::Unused,
+            };
+            items.push(item);
         }
 
-        // Handle special space (assuming you have the logic for this)
-        let special_space = None; // Placeholder, adjust as needed
+        let special = *header.pd_special as usize;
+        let special_space = if special > 0 && special < page.len() {
+            Some(SpecialSection {
+                data: page[special..].to_vec(),
+            })
+        } else {
+            None
+        };
 
         Ok(PageLayout {
+            block_number,
+            checksum,
             header,
             item_identifiers,
             items,
             special_space,
         })
     }
+
+    pub fn block_number(&self) -> BlockNumber {
+        self.block_number
+    }
+
+    pub fn checksum(&self) -> ChecksumStatus {
+        self.checksum
+    }
+
+    pub fn header(&self) -> &PageHeaderData {
+        &self.header
+    }
+
+    pub fn item_identifiers(&self) -> &[ItemIdData] {
+        &self.item_identifiers
+    }
+
+    pub fn items(&self) -> &[PageItem] {
+        &self.items
+    }
+
+    pub fn special_space(&self) -> Option<&SpecialSection> {
+        self.special_space.as_ref()
+    }
+}
+
+/// Returns the `lp_len` bytes stored at `lp_off`, checking they lie within the page.
+pub(crate) fn item_data<'a>(page: &'a [u8], item_id: &ItemIdData) -> io::Result<&'a [u8]> {
+    let start = item_id.lp_off as usize;
+    let end = start + item_id.lp_len as usize;
+    if end > page.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Line pointer at offset {} with length {} runs past the end of the page",
+                item_id.lp_off, item_id.lp_len
+            ),
+        ));
+    }
+    Ok(&page[start..end])
 }
 
 // Table structure
 #[derive(Debug)]
+#[allow(dead_code)]
 struct Table {
     rows: Vec<TableRow>,
 }
 
 // Index structure
 #[derive(Debug)]
+#[allow(dead_code)]
 struct Index {
     btree: BTreeIndex,
 }
@@ -261,7 +582,9 @@ pub fn read_page_header<R: Read>(
     reader: &mut R,
     endianness: Endianness,
 ) -> io::Result<PageHeaderData> {
-    let pd_lsn = PageXLogRecPtr(read_u64(reader, endianness)?);
+    let xlogid = read_u32(reader, endianness)?;
+    let xrecoff = read_u32(reader, endianness)?;
+    let pd_lsn = PageXLogRecPtr((xlogid as u64) << 32 | xrecoff as u64);
     let pd_checksum = read_u16(reader, endianness)?;
     let flags = read_u16(reader, endianness)?;
     let pd_flags = PageFlags::from_bits_truncate(flags);
@@ -288,7 +611,8 @@ pub fn read_item_identifiers<R: Read>(
     header: &PageHeaderData,
     endianness: Endianness,
 ) -> io::Result<Vec<ItemIdData>> {
-    let num_identifiers = (*header.pd_lower as usize - std::mem::size_of::<PageHeaderData>()) / 4; // assuming 4 bytes per ItemIdData
+    // The reader carries no length, so bound the page by the size its header records.
+    let num_identifiers = header.item_count(header.page_size())?;
 
     let mut item_identifiers = Vec::with_capacity(num_identifiers);
 
@@ -301,37 +625,70 @@ pub fn read_item_identifiers<R: Read>(
 }
 
 pub const DEFAULT_POSTGRES_PAGE_SIZE: usize = 8192; // Default Postgres page size in bytes
-pub fn read_all_pages<R: Read>(
+
+/// Size of the page header as written on disk (`SizeOfPageHeaderData`).
+pub const SIZE_OF_PAGE_HEADER_DATA: usize = 24;
+/// Size of one line pointer on disk.
+pub const SIZE_OF_ITEM_ID_DATA: usize = 4;
+/// Size of the fixed part of a heap tuple header on disk (`SizeofHeapTupleHeader`).
+pub const SIZE_OF_HEAP_TUPLE_HEADER: usize = 23;
+/// Smallest block size PostgreSQL can be built with (`--with-blocksize=1`).
+pub const MIN_POSTGRES_PAGE_SIZE: usize = 1024;
+/// Largest block size PostgreSQL can be built with (`--with-blocksize=32`).
+pub const MAX_POSTGRES_PAGE_SIZE: usize = 32768;
+
+/// Returns whether `block_size` is one PostgreSQL can be configured with.
+pub fn is_valid_block_size(block_size: usize) -> bool {
+    block_size.is_power_of_two()
+        && (MIN_POSTGRES_PAGE_SIZE..=MAX_POSTGRES_PAGE_SIZE).contains(&block_size)
+}
+
+/// Number of blocks in a 1 GiB segment file for the given block size (`RELSEG_SIZE`).
+pub fn relseg_size(block_size: usize) -> u32 {
+    (1024 * 1024 * 1024 / block_size) as u32
+}
+
+/// Determines the block size of a relation from the `pd_pagesize_version` of its first page.
+///
+/// Returns `None` if the relation is empty or its first page is new or damaged.
+/// The reader is left positioned where it started.
+pub fn detect_block_size<R: Read + Seek>(
     reader: &mut R,
     endianness: Endianness,
-) -> io::Result<Vec<PageLayout>> {
-    let mut pages = Vec::new();
-    let mut buffer = [0u8; DEFAULT_POSTGRES_PAGE_SIZE];
+) -> io::Result<Option<usize>> {
+    let start = reader.stream_position()?;
+    let header = read_page_header(reader, endianness);
+    reader.seek(SeekFrom::Start(start))?;
 
-    while let Ok(bytes_read) = reader.read(&mut buffer) {
-        if bytes_read == 0 {
-            break;
-        }
-        if bytes_read != DEFAULT_POSTGRES_PAGE_SIZE {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Incomplete page data",
-            ));
+    match header {
+        Ok(header) if !header.is_new() && is_valid_block_size(header.page_size()) => {
+            Ok(Some(header.page_size()))
         }
-
-        let mut cursor = io::Cursor::new(&buffer);
-        let page_layout = PageLayout::from_reader(&mut cursor, endianness)?;
-        pages.push(page_layout);
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
     }
+}
 
-    Ok(pages)
+/// Reads every page of a relation file into memory.
+///
+/// Uses `block_size` if given, otherwise the size recorded in the first page, falling
+/// back to `DEFAULT_POSTGRES_PAGE_SIZE`. Use `RelationReader` to stream large relations.
+pub fn read_all_pages<R: Read + Seek>(
+    reader: &mut R,
+    block_size: Option<usize>,
+    endianness: Endianness,
+) -> io::Result<Vec<PageLayout>> {
+    RelationReader::new(reader, block_size, endianness)?
+        .pages()
+        .collect()
 }
 
 use bitflags::bitflags;
 
 bitflags! {
-    #[derive(Debug)]
-    struct Infomask2: u16 {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Infomask2: u16 {
         const HEAP_NATTS_MASK = 0x07FF;
         const HEAP_KEYS_UPDATED = 0x2000;
         const HEAP_HOT_UPDATED = 0x4000;
@@ -341,8 +698,8 @@ bitflags! {
 }
 
 bitflags! {
-    #[derive(Debug)]
-    struct Infomask: u16 {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Infomask: u16 {
         const HEAP_HASNULL = 0x0001;
         const HEAP_HASVARWIDTH = 0x0002;
         const HEAP_HASEXTERNAL = 0x0004;
@@ -370,6 +727,8 @@ bitflags! {
 #[derive(Debug)]
 pub struct HeapTuple {
     header: HeapTupleHeaderData,
+    null_bitmap: Option<Vec<u8>>,
+    oid: Option<u32>,
     data: Vec<u8>,
 }
 
@@ -379,14 +738,119 @@ impl HeapTuple {
         total_length: u32,
         endianness: Endianness,
     ) -> io::Result<HeapTuple> {
+        if (total_length as usize) < SIZE_OF_HEAP_TUPLE_HEADER {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Tuple length {} is shorter than its header", total_length),
+            ));
+        }
         let header = HeapTupleHeaderData::read_from(reader, endianness)?;
 
         // Calculate the size of data by subtracting the size of the header from the total length.
-        let data_length = total_length as usize - std::mem::size_of::<HeapTupleHeaderData>();
-        let mut data = vec![0u8; data_length];
-        reader.read_exact(&mut data)?;
+        let data_length = total_length as usize - SIZE_OF_HEAP_TUPLE_HEADER;
+        let mut body = vec![0u8; data_length];
+        reader.read_exact(&mut body)?;
+        let sections = TupleSections::split(&header, &body, endianness)?;
+
+        Ok(HeapTuple {
+            null_bitmap: sections.null_bitmap.map(<[u8]>::to_vec),
+            oid: sections.oid,
+            data: sections.user_data.to_vec(),
+            header,
+        })
+    }
+
+    pub fn header(&self) -> &HeapTupleHeaderData {
+        &self.header
+    }
+
+    /// The null bitmap, present when `HEAP_HASNULL` is set.
+    pub fn null_bitmap(&self) -> Option<&[u8]> {
+        self.null_bitmap.as_deref()
+    }
+
+    /// The tuple OID of a table created `WITH OIDS` before PostgreSQL 12.
+    pub fn oid(&self) -> Option<u32> {
+        self.oid
+    }
+
+    /// User data, starting at `t_hoff`.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Whether the attribute at zero-based `index` is null.
+    pub fn is_null(&self, index: usize) -> bool {
+        att_isnull(self.null_bitmap(), self.header.natts(), index)
+    }
+}
+
+/// The variable parts of a tuple between the fixed header and the end of the tuple.
+pub(crate) struct TupleSections<'a> {
+    pub(crate) null_bitmap: Option<&'a [u8]>,
+    pub(crate) oid: Option<u32>,
+    pub(crate) user_data: &'a [u8],
+}
+
+impl<'a> TupleSections<'a> {
+    /// Splits `body`, the bytes following the fixed header, using `t_hoff`.
+    pub(crate) fn split(
+        header: &HeapTupleHeaderData,
+        body: &'a [u8],
+        endianness: Endianness,
+    ) -> io::Result<TupleSections<'a>> {
+        let t_hoff = header.t_hoff as usize;
+        let bitmap_len = header.null_bitmap_len();
+        let oid_len = if header.t_infomask.contains(Infomask::HEAP_HASOID_OLD) {
+            4
+        } else {
+            0
+        };
+        if t_hoff < SIZE_OF_HEAP_TUPLE_HEADER + bitmap_len + oid_len
+            || t_hoff > SIZE_OF_HEAP_TUPLE_HEADER + body.len()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "t_hoff {} does not fit a {}-byte null bitmap in a {}-byte tuple",
+                    t_hoff,
+                    bitmap_len,
+                    SIZE_OF_HEAP_TUPLE_HEADER + body.len()
+                ),
+            ));
+        }
+
+        let user_start = t_hoff - SIZE_OF_HEAP_TUPLE_HEADER;
+        let null_bitmap = (bitmap_len > 0).then(|| &body[..bitmap_len]);
+        // The old-style OID sits directly before the user data.
+        let oid = if oid_len > 0 {
+            Some(read_u32(
+                &mut &body[user_start - oid_len..user_start],
+                endianness,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(TupleSections {
+            null_bitmap,
+            oid,
+            user_data: &body[user_start..],
+        })
+    }
+}
 
-        Ok(HeapTuple { header, data })
+/// Whether the attribute at zero-based `index` is null (`att_isnull`).
+///
+/// Attributes past `natts` were added to the table after the tuple was written
+/// and read as null.
+pub fn att_isnull(null_bitmap: Option<&[u8]>, natts: u16, index: usize) -> bool {
+    if index >= natts as usize {
+        return true;
+    }
+    match null_bitmap {
+        Some(bitmap) => bitmap[index >> 3] & (1 << (index & 0x07)) == 0,
+        None => false,
     }
 }
 
@@ -428,4 +892,244 @@ impl HeapTupleHeaderData {
             t_hoff,
         })
     }
+
+    pub fn t_xmin(&self) -> &TransactionId {
+        &self.t_xmin
+    }
+
+    pub fn t_xmax(&self) -> &TransactionId {
+        &self.t_xmax
+    }
+
+    pub fn t_cid(&self) -> &CommandId {
+        &self.t_cid
+    }
+
+    pub fn t_ctid(&self) -> &ItemPointerData {
+        &self.t_ctid
+    }
+
+    pub fn t_infomask2(&self) -> &Infomask2 {
+        &self.t_infomask2
+    }
+
+    pub fn t_infomask(&self) -> &Infomask {
+        &self.t_infomask
+    }
+
+    pub fn t_hoff(&self) -> u8 {
+        self.t_hoff
+    }
+
+    /// Number of attributes stored in the tuple (`HeapTupleHeaderGetNatts`).
+    pub fn natts(&self) -> u16 {
+        self.t_infomask2.bits() & Infomask2::HEAP_NATTS_MASK.bits()
+    }
+
+    pub fn has_nulls(&self) -> bool {
+        self.t_infomask.contains(Infomask::HEAP_HASNULL)
+    }
+
+    /// Length of the null bitmap, one bit per attribute, or zero without `HEAP_HASNULL`.
+    pub fn null_bitmap_len(&self) -> usize {
+        if self.has_nulls() {
+            (self.natts() as usize).div_ceil(8)
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{heap_tuple, page_with_items};
+
+    // Line pointer words as they appear on disk in heap pages written by
+    // little-endian (x86_64) and big-endian (s390x) builds.
+
+    #[test]
+    fn item_id_little_endian() {
+        // First tuple on a fresh 8 KiB page: lp_off 8160, LP_NORMAL, lp_len 32.
+        let bytes = [0xe0, 0x9f, 0x40, 0x00];
+        let item = ItemIdData::from_reader(&mut &bytes[..], Endianness::LittleEndian).unwrap();
+        assert_eq!(item.lp_off(), 8160);
+        assert_eq!(*item.lp_flags(), LPFlags::LP_NORMAL);
+        assert_eq!(item.lp_len(), 32);
+
+        // Second tuple of 28 bytes directly below it.
+        let bytes = [0xc0, 0x9f, 0x38, 0x00];
+        let item = ItemIdData::from_reader(&mut &bytes[..], Endianness::LittleEndian).unwrap();
+        assert_eq!(item.lp_off(), 8128);
+        assert_eq!(*item.lp_flags(), LPFlags::LP_NORMAL);
+        assert_eq!(item.lp_len(), 28);
+
+        // HOT redirect to offset number 3.
+        let bytes = [0x03, 0x00, 0x01, 0x00];
+        let item = ItemIdData::from_reader(&mut &bytes[..], Endianness::LittleEndian).unwrap();
+        assert_eq!(item.lp_off(), 3);
+        assert_eq!(*item.lp_flags(), LPFlags::LP_REDIRECT);
+        assert_eq!(item.lp_len(), 0);
+
+        // Dead pointer with no storage.
+        let bytes = [0x00, 0x80, 0x01, 0x00];
+        let item = ItemIdData::from_reader(&mut &bytes[..], Endianness::LittleEndian).unwrap();
+        assert_eq!(item.lp_off(), 0);
+        assert_eq!(*item.lp_flags(), LPFlags::LP_DEAD);
+        assert_eq!(item.lp_len(), 0);
+    }
+
+    #[test]
+    fn item_id_big_endian() {
+        let bytes = [0x3f, 0xc0, 0x80, 0x20];
+        let item = ItemIdData::from_reader(&mut &bytes[..], Endianness::BigEndian).unwrap();
+        assert_eq!(item.lp_off(), 8160);
+        assert_eq!(*item.lp_flags(), LPFlags::LP_NORMAL);
+        assert_eq!(item.lp_len(), 32);
+
+        let bytes = [0x3f, 0x80, 0x80, 0x1c];
+        let item = ItemIdData::from_reader(&mut &bytes[..], Endianness::BigEndian).unwrap();
+        assert_eq!(item.lp_off(), 8128);
+        assert_eq!(*item.lp_flags(), LPFlags::LP_NORMAL);
+        assert_eq!(item.lp_len(), 28);
+
+        let bytes = [0x00, 0x07, 0x00, 0x00];
+        let item = ItemIdData::from_reader(&mut &bytes[..], Endianness::BigEndian).unwrap();
+        assert_eq!(item.lp_off(), 3);
+        assert_eq!(*item.lp_flags(), LPFlags::LP_REDIRECT);
+        assert_eq!(item.lp_len(), 0);
+
+        let bytes = [0x00, 0x01, 0x80, 0x00];
+        let item = ItemIdData::from_reader(&mut &bytes[..], Endianness::BigEndian).unwrap();
+        assert_eq!(item.lp_off(), 0);
+        assert_eq!(*item.lp_flags(), LPFlags::LP_DEAD);
+        assert_eq!(item.lp_len(), 0);
+    }
+
+    #[test]
+    fn resolves_items_at_lp_off() {
+        // The first line pointer points above the second, with a gap between them.
+        let first = heap_tuple(100, 0, Infomask::empty(), 1, &7u32.to_le_bytes());
+        let second = heap_tuple(100, 0, Infomask::empty(), 1, &9u32.to_le_bytes());
+        let page = page_with_items(&[(6000, &first), (4000, &second)]);
+        let layout =
+            PageLayout::from_bytes(&page, BlockNumber(0), Endianness::LittleEndian).unwrap();
+        let data: Vec<&[u8]> = layout
+            .items()
+            .iter()
+            .map(|item| match item {
+                PageItem::Normal(tuple) => tuple.data(),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(data, [&7u32.to_le_bytes()[..], &9u32.to_le_bytes()[..]]);
+
+        // A tuple running past the end of the page is an error, not a panic.
+        let page = page_with_items(&[(6000, &first), (8180, &second)]);
+        assert!(PageLayout::from_bytes(&page, BlockNumber(0), Endianness::LittleEndian).is_err());
+    }
+
+    #[test]
+    fn rejects_inverted_page_bounds() {
+        let mut page = [0u8; DEFAULT_POSTGRES_PAGE_SIZE];
+        // pd_lower 16 lies inside the 24-byte header.
+        page[12..14].copy_from_slice(&16u16.to_le_bytes());
+        page[14..16].copy_from_slice(&8192u16.to_le_bytes());
+        page[16..18].copy_from_slice(&8192u16.to_le_bytes());
+        assert!(PageLayout::from_bytes(&page, BlockNumber(0), Endianness::LittleEndian).is_err());
+
+        // pd_lower above pd_upper.
+        page[12..14].copy_from_slice(&4000u16.to_le_bytes());
+        page[14..16].copy_from_slice(&3000u16.to_le_bytes());
+        assert!(PageLayout::from_bytes(&page, BlockNumber(0), Endianness::LittleEndian).is_err());
+
+        // An all-zero page is new, not corrupt.
+        let page = [0u8; DEFAULT_POSTGRES_PAGE_SIZE];
+        let layout =
+            PageLayout::from_bytes(&page, BlockNumber(0), Endianness::LittleEndian).unwrap();
+        assert!(layout.items().is_empty());
+    }
+
+    #[test]
+    fn page_lsn_halves() {
+        // LSN 1/16B3748: xlogid 1, then xrecoff 0x016B3748, each in the
+        // machine's byte order.
+        let mut little = [0u8; SIZE_OF_PAGE_HEADER_DATA];
+        little[0..4].copy_from_slice(&1u32.to_le_bytes());
+        little[4..8].copy_from_slice(&0x016B_3748u32.to_le_bytes());
+        let mut big = [0u8; SIZE_OF_PAGE_HEADER_DATA];
+        big[0..4].copy_from_slice(&1u32.to_be_bytes());
+        big[4..8].copy_from_slice(&0x016B_3748u32.to_be_bytes());
+
+        for (bytes, endianness) in [
+            (little, Endianness::LittleEndian),
+            (big, Endianness::BigEndian),
+        ] {
+            let header = read_page_header(&mut &bytes[..], endianness).unwrap();
+            assert_eq!(*header.pd_lsn(), PageXLogRecPtr(0x1_016B_3748));
+            assert_eq!(header.pd_lsn().to_string(), "1/16B3748");
+        }
+    }
+
+    #[test]
+    fn detects_block_size_from_first_page() {
+        let mut page = page_with_items(&[]);
+        page[18..20].copy_from_slice(&(4096u16 | 4).to_le_bytes());
+        let mut file = io::Cursor::new(page);
+        assert_eq!(
+            detect_block_size(&mut file, Endianness::LittleEndian).unwrap(),
+            Some(4096)
+        );
+        // The header is peeked at, not consumed.
+        assert_eq!(file.position(), 0);
+
+        let mut new_page = io::Cursor::new(vec![0u8; DEFAULT_POSTGRES_PAGE_SIZE]);
+        assert_eq!(
+            detect_block_size(&mut new_page, Endianness::LittleEndian).unwrap(),
+            None
+        );
+
+        let mut short = io::Cursor::new(vec![0xffu8; SIZE_OF_PAGE_HEADER_DATA - 1]);
+        assert_eq!(
+            detect_block_size(&mut short, Endianness::LittleEndian).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn splits_null_bitmap_oid_and_user_data() {
+        // Ten attributes, the second and ninth null, with an old-style OID: the
+        // bitmap follows the 23-byte header, the OID ends at t_hoff 32.
+        let mut tuple = vec![0u8; 32];
+        tuple[18..20].copy_from_slice(&10u16.to_le_bytes());
+        let infomask = Infomask::HEAP_HASNULL | Infomask::HEAP_HASOID_OLD;
+        tuple[20..22].copy_from_slice(&infomask.bits().to_le_bytes());
+        tuple[22] = 32;
+        tuple[23] = 0b1111_1101;
+        tuple[24] = 0b0000_0010;
+        tuple[28..32].copy_from_slice(&16400u32.to_le_bytes());
+        tuple.extend_from_slice(&[1, 2, 3, 4]);
+
+        let tuple = HeapTuple::from_reader(
+            &mut &tuple[..],
+            tuple.len() as u32,
+            Endianness::LittleEndian,
+        )
+        .unwrap();
+        assert_eq!(tuple.header().natts(), 10);
+        assert_eq!(tuple.header().null_bitmap_len(), 2);
+        assert_eq!(tuple.null_bitmap(), Some(&[0b1111_1101, 0b0000_0010][..]));
+        assert_eq!(tuple.oid(), Some(16400));
+        assert_eq!(tuple.data(), [1, 2, 3, 4]);
+        let nulls: Vec<usize> = (0..12).filter(|&index| tuple.is_null(index)).collect();
+        // Attributes past natts read as null too.
+        assert_eq!(nulls, [1, 8, 10, 11]);
+
+        // A t_hoff leaving no room for the bitmap and OID is rejected.
+        let mut short = [0u8; 28];
+        short[18..20].copy_from_slice(&10u16.to_le_bytes());
+        short[20..22].copy_from_slice(&infomask.bits().to_le_bytes());
+        short[22] = 24;
+        assert!(HeapTuple::from_reader(&mut &short[..], 28, Endianness::LittleEndian).is_err());
+    }
 }
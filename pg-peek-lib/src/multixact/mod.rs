@@ -0,0 +1,303 @@
+//! MultiXacts from `pg_multixact/offsets` and `pg_multixact/members` (`multixact.c`).
+//!
+//! When several transactions lock a tuple, or one locks and another updates it,
+//! `t_xmax` holds a MultiXactId. `offsets` maps each MultiXactId to the position of
+//! its first member; the members run up to the next MultiXact's offset. `members`
+//! stores them in groups of four xids preceded by a word of one status byte each.
+
+use crate::slru::SlruReader;
+use crate::{Endianness, HeapTupleHeaderData, Infomask, Infomask2, TransactionId};
+use std::io;
+use std::path::Path;
+use strum_macros::Display;
+
+/// Size of a `MultiXactOffset`.
+const SIZE_OF_OFFSET: usize = 4;
+/// Members per group (`MULTIXACT_MEMBERS_PER_MEMBERGROUP`).
+const MEMBERS_PER_GROUP: u32 = 4;
+/// Bytes of status flags ahead of each group (`MULTIXACT_FLAGBYTES_PER_GROUP`).
+const FLAG_BYTES_PER_GROUP: usize = 4;
+/// Size of a member group (`MULTIXACT_MEMBERGROUP_SIZE`).
+const GROUP_SIZE: usize = FLAG_BYTES_PER_GROUP + MEMBERS_PER_GROUP as usize * 4;
+/// Sanity limit on members per MultiXact, to stop at corrupt offsets.
+const MAX_MEMBERS: u32 = 1 << 20;
+/// Bits of status per member (`MXACT_MEMBER_BITS_PER_XACT`).
+const BITS_PER_MEMBER: u32 = 8;
+
+/// Lock mode or update kind of one member (`MultiXactStatus`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum MultiXactStatus {
+    ForKeyShare,
+    ForShare,
+    ForNoKeyUpdate,
+    ForUpdate,
+    /// An update that changed no key columns.
+    NoKeyUpdate,
+    Update,
+}
+
+impl MultiXactStatus {
+    fn from_id(id: u8) -> io::Result<MultiXactStatus> {
+        Ok(match id {
+            0 => MultiXactStatus::ForKeyShare,
+            1 => MultiXactStatus::ForShare,
+            2 => MultiXactStatus::ForNoKeyUpdate,
+            3 => MultiXactStatus::ForUpdate,
+            4 => MultiXactStatus::NoKeyUpdate,
+            5 => MultiXactStatus::Update,
+            id => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid MultiXact member status {}", id),
+                ))
+            }
+        })
+    }
+
+    /// Whether the member updated or deleted the tuple rather than only locking it.
+    pub fn is_update(&self) -> bool {
+        matches!(self, MultiXactStatus::NoKeyUpdate | MultiXactStatus::Update)
+    }
+}
+
+/// One transaction of a MultiXact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultiXactMember {
+    xid: TransactionId,
+    status: MultiXactStatus,
+}
+
+impl MultiXactMember {
+    pub fn new(xid: TransactionId, status: MultiXactStatus) -> MultiXactMember {
+        MultiXactMember { xid, status }
+    }
+
+    pub fn xid(&self) -> TransactionId {
+        self.xid
+    }
+
+    pub fn status(&self) -> MultiXactStatus {
+        self.status
+    }
+}
+
+/// Reads MultiXacts from a `pg_multixact` directory.
+pub struct MultiXactReader {
+    offsets: SlruReader,
+    members: SlruReader,
+    endianness: Endianness,
+    /// Next MultiXactId and member offset to be assigned, if known.
+    next: Option<(u32, u32)>,
+}
+
+impl MultiXactReader {
+    /// Reads the `pg_multixact` directory of a data directory.
+    pub fn open(
+        data_directory: &Path,
+        block_size: usize,
+        endianness: Endianness,
+    ) -> MultiXactReader {
+        let dir = data_directory.join("pg_multixact");
+        MultiXactReader {
+            offsets: SlruReader::new(&dir.join("offsets"), block_size),
+            members: SlruReader::new(&dir.join("members"), block_size),
+            endianness,
+            next: None,
+        }
+    }
+
+    /// Records the next MultiXactId and member offset to be assigned, such as
+    /// `CheckPoint::next_multi` and `next_multi_offset` from `pg_control`.
+    ///
+    /// The newest MultiXact has no successor in `offsets` yet; without this its
+    /// member count cannot be known.
+    pub fn set_next(&mut self, next_multi: u32, next_offset: u32) {
+        self.next = Some((next_multi, next_offset));
+    }
+
+    fn offsets_per_page(&self) -> u32 {
+        (self.offsets.block_size() / SIZE_OF_OFFSET) as u32
+    }
+
+    fn groups_per_page(&self) -> u32 {
+        (self.members.block_size() / GROUP_SIZE) as u32
+    }
+
+    /// Offset of `multi`'s first member (`MultiXactIdToOffsetPage` and friends).
+    fn offset(&self, multi: u32) -> io::Result<u32> {
+        let entry = (multi % self.offsets_per_page()) as usize * SIZE_OF_OFFSET;
+        let endianness = self.endianness;
+        self.offsets
+            .with_page(multi / self.offsets_per_page(), |page| {
+                crate::read_u32(&mut &page[entry..], endianness)
+            })?
+    }
+
+    /// Member at `offset`, or `None` for the unused slot at offset zero.
+    fn member(&self, offset: u32) -> io::Result<Option<MultiXactMember>> {
+        let group = offset / MEMBERS_PER_GROUP;
+        let index = offset % MEMBERS_PER_GROUP;
+        let flags_offset = (group % self.groups_per_page()) as usize * GROUP_SIZE;
+        let xid_offset = flags_offset + FLAG_BYTES_PER_GROUP + index as usize * 4;
+        let endianness = self.endianness;
+
+        let (flags, xid) = self
+            .members
+            .with_page(group / self.groups_per_page(), |page| {
+                Ok::<_, io::Error>((
+                    crate::read_u32(&mut &page[flags_offset..], endianness)?,
+                    crate::read_u32(&mut &page[xid_offset..], endianness)?,
+                ))
+            })??;
+        if xid == 0 {
+            return Ok(None);
+        }
+        let status = (flags >> (index * BITS_PER_MEMBER)) as u8;
+        Ok(Some(MultiXactMember::new(
+            TransactionId(xid),
+            MultiXactStatus::from_id(status)?,
+        )))
+    }
+
+    /// The members of `multi` (`GetMultiXactIdMembers`).
+    pub fn members(&self, multi: u32) -> io::Result<Vec<MultiXactMember>> {
+        if multi == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "MultiXactId 0 is invalid",
+            ));
+        }
+        let offset = self.offset(multi)?;
+
+        // MultiXactIds skip zero when they wrap around.
+        let next_multi = match multi.wrapping_add(1) {
+            0 => 1,
+            next => next,
+        };
+        // The next offset to assign may be zero after wraparound; a stored one never is.
+        let next_offset = match self.next {
+            Some((next, next_offset)) if next == next_multi => Some(next_offset),
+            _ => Some(self.offset(next_multi)?).filter(|&next_offset| next_offset != 0),
+        };
+        let next_offset = match next_offset {
+            Some(next_offset) if offset != 0 => next_offset,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("MultiXact {} has no recorded member offsets", multi),
+                ))
+            }
+        };
+
+        // Offsets wrap around too; the slot at offset zero is left unused.
+        let length = next_offset.wrapping_sub(offset);
+        if length > MAX_MEMBERS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "MultiXact {} spans offsets {} to {}, too many members",
+                    multi, offset, next_offset
+                ),
+            ));
+        }
+        let mut members = Vec::new();
+        for index in 0..length {
+            if let Some(member) = self.member(offset.wrapping_add(index))? {
+                members.push(member);
+            }
+        }
+        Ok(members)
+    }
+
+    /// The member that updated or deleted the tuple, if any (`MultiXactIdGetUpdateXid`).
+    pub fn updater(&self, multi: u32) -> io::Result<Option<TransactionId>> {
+        Ok(self
+            .members(multi)?
+            .into_iter()
+            .find(|member| member.status.is_update())
+            .map(|member| member.xid))
+    }
+}
+
+/// The transactions holding `t_xmax` of a tuple and what each did to it.
+///
+/// A plain xid's lock mode comes from the infomask bits; a MultiXact is expanded
+/// through `multixact`. A tuple whose `t_xmax` is unset or invalid has none.
+pub fn xmax_members(
+    header: &HeapTupleHeaderData,
+    multixact: &MultiXactReader,
+) -> io::Result<Vec<MultiXactMember>> {
+    let infomask = header.t_infomask();
+    let xmax = *header.t_xmax();
+    if infomask.contains(Infomask::HEAP_XMAX_INVALID) || xmax == TransactionId::INVALID {
+        return Ok(Vec::new());
+    }
+    if infomask.contains(Infomask::HEAP_XMAX_IS_MULTI) {
+        return multixact.members(xmax.0);
+    }
+    Ok(vec![MultiXactMember::new(xmax, xmax_lock_mode(header))])
+}
+
+/// Lock mode of a plain xid in `t_xmax` (`get_mxact_status_for_lock` in reverse).
+pub fn xmax_lock_mode(header: &HeapTupleHeaderData) -> MultiXactStatus {
+    let infomask = header.t_infomask();
+    let keys_updated = header.t_infomask2().contains(Infomask2::HEAP_KEYS_UPDATED);
+    if !crate::visibility::xmax_is_locked_only(infomask) {
+        return if keys_updated {
+            MultiXactStatus::Update
+        } else {
+            MultiXactStatus::NoKeyUpdate
+        };
+    }
+    match infomask.intersection(Infomask::HEAP_LOCK_MASK) {
+        mask if mask == Infomask::HEAP_XMAX_SHR_LOCK => MultiXactStatus::ForShare,
+        mask if mask == Infomask::HEAP_XMAX_KEYSHR_LOCK => MultiXactStatus::ForKeyShare,
+        _ if keys_updated => MultiXactStatus::ForUpdate,
+        _ => MultiXactStatus::ForNoKeyUpdate,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_members_across_groups() {
+        let dir = tempfile::tempdir().unwrap();
+        let block_size = 8192;
+        let multixact = dir.path().join("pg_multixact");
+        std::fs::create_dir_all(multixact.join("offsets")).unwrap();
+        std::fs::create_dir_all(multixact.join("members")).unwrap();
+
+        // Multi 1 has members at offsets 1..=4, spilling into the second group.
+        let mut offsets = vec![0u8; block_size];
+        offsets[4..8].copy_from_slice(&1u32.to_le_bytes());
+        offsets[8..12].copy_from_slice(&5u32.to_le_bytes());
+        std::fs::write(multixact.join("offsets").join("0000"), &offsets).unwrap();
+
+        let mut members = vec![0u8; block_size];
+        members[0..4].copy_from_slice(&[0, 0, 1, 5]);
+        for (slot, xid) in [(1, 700u32), (2, 701), (3, 702)] {
+            members[4 + slot * 4..8 + slot * 4].copy_from_slice(&xid.to_le_bytes());
+        }
+        members[20] = 3;
+        members[24..28].copy_from_slice(&703u32.to_le_bytes());
+        std::fs::write(multixact.join("members").join("0000"), &members).unwrap();
+
+        let reader = MultiXactReader::open(dir.path(), block_size, Endianness::LittleEndian);
+        let members = reader.members(1).unwrap();
+        assert_eq!(
+            members,
+            [
+                MultiXactMember::new(TransactionId(700), MultiXactStatus::ForKeyShare),
+                MultiXactMember::new(TransactionId(701), MultiXactStatus::ForShare),
+                MultiXactMember::new(TransactionId(702), MultiXactStatus::Update),
+                MultiXactMember::new(TransactionId(703), MultiXactStatus::ForUpdate),
+            ]
+        );
+        assert_eq!(reader.updater(1).unwrap(), Some(TransactionId(702)));
+        // Multi 2 is the newest; its end comes from the next offset to assign.
+        assert!(reader.members(2).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Port of PostgreSQL's pglz decompressor (src/common/pg_lzcompress.c).
+//!
+//! The compressed stream is a sequence of control bytes, each followed by up to
+//! eight items. A clear control bit means a literal byte; a set bit means a
+//! back-reference of two or three bytes: a 12-bit offset into the output written
+//! so far and a length of 3 to 273 bytes.
+
+use std::io;
+
+/// Largest size of a palloc'd chunk, and so of a varlena value (`MaxAllocSize`).
+pub(crate) const MAX_ALLOC_SIZE: usize = 0x3FFF_FFFF;
+/// Most output bytes a byte of input can yield: a three-byte tag copies 273.
+const MAX_EXPANSION: usize = 91;
+
+fn corrupt(message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Compressed data is corrupt: {}", message),
+    )
+}
+
+/// Decompresses pglz data that expands to exactly `raw_size` bytes.
+///
+/// Malformed input, including input that stops short of or runs past `raw_size`,
+/// is reported as an `InvalidData` error. So is a `raw_size` that `source`
+/// could not expand to, before anything is allocated for it.
+pub fn pglz_decompress(source: &[u8], raw_size: usize) -> io::Result<Vec<u8>> {
+    if raw_size > MAX_ALLOC_SIZE || raw_size > source.len().saturating_mul(MAX_EXPANSION) {
+        return Err(corrupt(format!(
+            "{} bytes cannot expand to {} bytes",
+            source.len(),
+            raw_size
+        )));
+    }
+    let mut dest = Vec::with_capacity(raw_size);
+    let mut sp = 0;
+
+    while sp < source.len() && dest.len() < raw_size {
+        let mut ctrl = source[sp];
+        sp += 1;
+
+        for _ in 0..8 {
+            if sp >= source.len() || dest.len() >= raw_size {
+                break;
+            }
+
+            if ctrl & 1 != 0 {
+                // Back-reference: length in the low nibble of the first byte, offset in
+                // its high nibble and the second byte, optional extra length byte.
+                let tag = source
+                    .get(sp..sp + 2)
+                    .ok_or_else(|| corrupt(format!("truncated tag at byte {}", sp)))?;
+                let mut len = (tag[0] & 0x0f) as usize + 3;
+                let off = ((tag[0] & 0xf0) as usize) << 4 | tag[1] as usize;
+                sp += 2;
+                if len == 18 {
+                    let extra = *source
+                        .get(sp)
+                        .ok_or_else(|| corrupt(format!("truncated length at byte {}", sp)))?;
+                    len += extra as usize;
+                    sp += 1;
+                }
+
+                if off == 0 || off > dest.len() {
+                    return Err(corrupt(format!(
+                        "offset {} with {} bytes decompressed",
+                        off,
+                        dest.len()
+                    )));
+                }
+
+                // The match may overlap the bytes it produces, so copy one at a time.
+                let len = len.min(raw_size - dest.len());
+                let start = dest.len() - off;
+                for index in start..start + len {
+                    let byte = dest[index];
+                    dest.push(byte);
+                }
+            } else {
+                dest.push(source[sp]);
+                sp += 1;
+            }
+
+            ctrl >>= 1;
+        }
+    }
+
+    if dest.len() != raw_size || sp != source.len() {
+        return Err(corrupt(format!(
+            "produced {} of {} bytes from {} of {} input bytes",
+            dest.len(),
+            raw_size,
+            sp,
+            source.len()
+        )));
+    }
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals_and_overlapping_match() {
+        // "abc" as literals, then a match of 9 bytes at offset 3.
+        let source = [0b0000_1000, b'a', b'b', b'c', 0x06, 0x03];
+        assert_eq!(pglz_decompress(&source, 12).unwrap(), b"abcabcabcabc");
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        // Offset reaching before the start of the output.
+        assert!(pglz_decompress(&[0b0000_0010, b'a', 0x00, 0x05], 4).is_err());
+        // Truncated tag.
+        assert!(pglz_decompress(&[0b0000_0001, 0x00], 4).is_err());
+        // Too little output for the declared size.
+        assert!(pglz_decompress(&[0x00, b'a'], 4).is_err());
+        // Trailing input after the declared size.
+        assert!(pglz_decompress(&[0x00, b'a', b'b'], 1).is_err());
+        // Declared size out of reach of the input, or past the varlena maximum.
+        assert!(pglz_decompress(&[0x00, b'a'], 183).is_err());
+        assert!(pglz_decompress(&[0u8; 1 << 24], MAX_ALLOC_SIZE + 1).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! Relation access through a read-only memory mapping.
+
+use crate::view::PageRef;
+use crate::{
+    detect_block_size, is_valid_block_size, BlockNumber, Endianness, DEFAULT_POSTGRES_PAGE_SIZE,
+};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// A relation file mapped into memory, handing out borrowed pages.
+pub struct MmapRelation {
+    mmap: Mmap,
+    block_size: usize,
+    endianness: Endianness,
+}
+
+impl MmapRelation {
+    /// Maps the relation file at `path`, choosing the block size like `RelationReader::new`.
+    ///
+    /// The file must not be truncated while mapped; point this at a cold copy or a
+    /// stopped cluster.
+    pub fn open(
+        path: &Path,
+        block_size: Option<usize>,
+        endianness: Endianness,
+    ) -> io::Result<MmapRelation> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is read-only and callers are told not to modify the file
+        // while it is open.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let block_size = match block_size {
+            Some(block_size) => block_size,
+            None => detect_block_size(&mut io::Cursor::new(&mmap[..]), endianness)?
+                .unwrap_or(DEFAULT_POSTGRES_PAGE_SIZE),
+        };
+        if !is_valid_block_size(block_size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid block size {}", block_size),
+            ));
+        }
+
+        Ok(MmapRelation {
+            mmap,
+            block_size,
+            endianness,
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Number of complete blocks in the relation.
+    pub fn n_blocks(&self) -> u32 {
+        (self.mmap.len() / self.block_size) as u32
+    }
+
+    /// Bytes after the last complete block; non-zero means the file was truncated mid-page.
+    pub fn trailing_bytes(&self) -> usize {
+        self.mmap.len() % self.block_size
+    }
+
+    /// Borrows one block.
+    pub fn page(&self, block: BlockNumber) -> io::Result<PageRef<'_>> {
+        if *block >= self.n_blocks() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Block {} is beyond the end of the relation ({} blocks)",
+                    *block,
+                    self.n_blocks()
+                ),
+            ));
+        }
+        let start = *block as usize * self.block_size;
+        PageRef::new(
+            &self.mmap[start..start + self.block_size],
+            block,
+            self.endianness,
+        )
+    }
+
+    /// Iterates over every complete block.
+    pub fn pages(&self) -> impl Iterator<Item = io::Result<PageRef<'_>>> + '_ {
+        (0..self.n_blocks()).map(move |block| self.page(BlockNumber(block)))
+    }
+}
@@ -0,0 +1,270 @@
+//! Block-at-a-time access to a relation file.
+
+pub mod mmap;
+pub mod segment;
+
+use crate::{
+    detect_block_size, is_valid_block_size, BlockNumber, Endianness, PageLayout,
+    DEFAULT_POSTGRES_PAGE_SIZE,
+};
+use segment::SegmentedFile;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+/// Reads pages of a relation from any seekable source, one block at a time.
+pub struct RelationReader<R> {
+    reader: R,
+    block_size: usize,
+    endianness: Endianness,
+    n_blocks: u32,
+    trailing_bytes: usize,
+}
+
+impl<R: Read + Seek> RelationReader<R> {
+    /// Opens a relation, using `block_size` if given, otherwise the size recorded
+    /// in the first page, falling back to `DEFAULT_POSTGRES_PAGE_SIZE`.
+    pub fn new(
+        mut reader: R,
+        block_size: Option<usize>,
+        endianness: Endianness,
+    ) -> io::Result<RelationReader<R>> {
+        reader.seek(SeekFrom::Start(0))?;
+        let block_size = match block_size {
+            Some(block_size) => block_size,
+            None => {
+                detect_block_size(&mut reader, endianness)?.unwrap_or(DEFAULT_POSTGRES_PAGE_SIZE)
+            }
+        };
+        if !is_valid_block_size(block_size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid block size {}", block_size),
+            ));
+        }
+
+        let length = reader.seek(SeekFrom::End(0))?;
+        let n_blocks = u32::try_from(length / block_size as u64).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "Relation has too many blocks")
+        })?;
+        let trailing_bytes = (length % block_size as u64) as usize;
+
+        Ok(RelationReader {
+            reader,
+            block_size,
+            endianness,
+            n_blocks,
+            trailing_bytes,
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Number of complete blocks in the relation.
+    pub fn n_blocks(&self) -> u32 {
+        self.n_blocks
+    }
+
+    /// Bytes after the last complete block; non-zero means the file was truncated mid-page.
+    pub fn trailing_bytes(&self) -> usize {
+        self.trailing_bytes
+    }
+
+    /// Reads the raw bytes of one block into `buffer`, which must be `block_size` long.
+    pub fn read_block_bytes(&mut self, block: BlockNumber, buffer: &mut [u8]) -> io::Result<()> {
+        if *block >= self.n_blocks {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Block {} is beyond the end of the relation ({} blocks)",
+                    *block, self.n_blocks
+                ),
+            ));
+        }
+        self.reader
+            .seek(SeekFrom::Start(*block as u64 * self.block_size as u64))?;
+        self.reader.read_exact(buffer)
+    }
+
+    /// Reads and decodes one block.
+    pub fn read_block(&mut self, block: BlockNumber) -> io::Result<PageLayout> {
+        let mut buffer = vec![0u8; self.block_size];
+        self.read_block_bytes(block, &mut buffer)?;
+        PageLayout::from_bytes(&buffer, block, self.endianness)
+    }
+
+    /// Iterates over every block, reporting a truncated final page as an error.
+    pub fn pages(&mut self) -> PageIter<'_, R> {
+        let end = BlockNumber(self.n_blocks);
+        let mut iter = self.range(BlockNumber(0)..end);
+        iter.check_trailing = true;
+        iter
+    }
+
+    /// Iterates over the blocks in `blocks`, clamped to the end of the relation.
+    pub fn range(&mut self, blocks: Range<BlockNumber>) -> PageIter<'_, R> {
+        let end = (*blocks.end).min(self.n_blocks);
+        PageIter {
+            buffer: vec![0u8; self.block_size],
+            relation: self,
+            next: *blocks.start,
+            end,
+            check_trailing: false,
+        }
+    }
+}
+
+impl RelationReader<SegmentedFile> {
+    /// Opens a relation fork by the path of its first segment, chaining `path.1`,
+    /// `path.2`, ... so block numbers run across the whole relation.
+    ///
+    /// `relseg_size` defaults to 1 GiB worth of blocks.
+    pub fn open_segments(
+        path: &Path,
+        block_size: Option<usize>,
+        relseg_size: Option<u32>,
+        endianness: Endianness,
+    ) -> io::Result<RelationReader<SegmentedFile>> {
+        let block_size = match block_size {
+            Some(block_size) => block_size,
+            None => detect_block_size(&mut File::open(path)?, endianness)?
+                .unwrap_or(DEFAULT_POSTGRES_PAGE_SIZE),
+        };
+        if !is_valid_block_size(block_size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid block size {}", block_size),
+            ));
+        }
+        let relseg_size = relseg_size.unwrap_or_else(|| crate::relseg_size(block_size));
+
+        let segments = SegmentedFile::open(path, block_size, relseg_size)?;
+        RelationReader::new(segments, Some(block_size), endianness)
+    }
+
+    /// The chained segment files.
+    pub fn segments(&self) -> &SegmentedFile {
+        &self.reader
+    }
+}
+
+/// Iterator yielding one decoded page at a time, reusing a single block buffer.
+pub struct PageIter<'a, R> {
+    relation: &'a mut RelationReader<R>,
+    buffer: Vec<u8>,
+    next: u32,
+    end: u32,
+    check_trailing: bool,
+}
+
+impl<R: Read + Seek> Iterator for PageIter<'_, R> {
+    type Item = io::Result<PageLayout>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.end {
+            if self.check_trailing && self.relation.trailing_bytes > 0 {
+                self.check_trailing = false;
+                return Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Incomplete page data: {} bytes after the last complete block",
+                        self.relation.trailing_bytes
+                    ),
+                )));
+            }
+            return None;
+        }
+
+        let block = BlockNumber(self.next);
+        self.next += 1;
+        let page = self
+            .relation
+            .read_block_bytes(block, &mut self.buffer)
+            .and_then(|_| PageLayout::from_bytes(&self.buffer, block, self.relation.endianness));
+        Some(page)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end.saturating_sub(self.next) as usize;
+        (remaining, Some(remaining + self.check_trailing as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SIZE_OF_PAGE_HEADER_DATA;
+    use std::io::Cursor;
+
+    const BLOCK: usize = 1024;
+
+    /// Three empty pages whose LSNs are their block numbers, then `trailing` bytes.
+    fn relation(trailing: usize) -> Cursor<Vec<u8>> {
+        let mut data = Vec::new();
+        for block in 0..3u32 {
+            let mut page = vec![0u8; BLOCK];
+            page[4..8].copy_from_slice(&block.to_le_bytes());
+            page[12..14].copy_from_slice(&(SIZE_OF_PAGE_HEADER_DATA as u16).to_le_bytes());
+            page[14..16].copy_from_slice(&(BLOCK as u16).to_le_bytes());
+            page[16..18].copy_from_slice(&(BLOCK as u16).to_le_bytes());
+            page[18..20].copy_from_slice(&(BLOCK as u16 | 4).to_le_bytes());
+            data.extend(page);
+        }
+        data.extend(vec![0u8; trailing]);
+        Cursor::new(data)
+    }
+
+    fn lsns(pages: impl Iterator<Item = io::Result<PageLayout>>) -> Vec<io::Result<u64>> {
+        pages
+            .map(|page| page.map(|page| **page.header().pd_lsn()))
+            .collect()
+    }
+
+    #[test]
+    fn iterates_ranges_and_reports_trailing_bytes() {
+        let mut reader = RelationReader::new(relation(0), None, Endianness::LittleEndian).unwrap();
+        assert_eq!(reader.block_size(), BLOCK);
+        assert_eq!(reader.n_blocks(), 3);
+        let read: Vec<u64> = lsns(reader.pages())
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(read, [0, 1, 2]);
+
+        // Ranges are clamped to the end of the relation.
+        let read: Vec<u64> = lsns(reader.range(BlockNumber(1)..BlockNumber(10)))
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(read, [1, 2]);
+        assert_eq!(reader.range(BlockNumber(5)..BlockNumber(10)).count(), 0);
+        assert!(reader.read_block(BlockNumber(3)).is_err());
+        assert_eq!(
+            **reader.read_block(BlockNumber(2)).unwrap().header().pd_lsn(),
+            2
+        );
+
+        let mut reader =
+            RelationReader::new(relation(100), Some(BLOCK), Endianness::LittleEndian).unwrap();
+        assert_eq!(reader.n_blocks(), 3);
+        assert_eq!(reader.trailing_bytes(), 100);
+        let read = lsns(reader.pages());
+        assert_eq!(read.len(), 4);
+        assert!(read[..3].iter().all(Result::is_ok));
+        assert_eq!(
+            read[3].as_ref().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        // A bounded range stops at its end without the error.
+        assert!(lsns(reader.range(BlockNumber(0)..BlockNumber(3)))
+            .iter()
+            .all(Result::is_ok));
+    }
+}
@@ -0,0 +1,235 @@
+//! Relations larger than one segment are split into `16384`, `16384.1`, `16384.2`, ...
+
+use crate::BlockNumber;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+struct Segment {
+    path: PathBuf,
+    file: File,
+    len: u64,
+}
+
+/// All segments of one relation fork, read and seeked as one contiguous file.
+pub struct SegmentedFile {
+    segments: Vec<Segment>,
+    segment_bytes: u64,
+    position: u64,
+}
+
+impl SegmentedFile {
+    /// Opens the segment at `path` and every `path.N` following it.
+    ///
+    /// Every segment but the last must hold exactly `relseg_size` blocks; a short
+    /// segment, or a gap in the numbering, is reported as corruption.
+    pub fn open(path: &Path, block_size: usize, relseg_size: u32) -> io::Result<SegmentedFile> {
+        let segment_bytes = relseg_size as u64 * block_size as u64;
+        let last_segment = last_segment_number(path)?;
+
+        let mut segments = Vec::new();
+        for number in 0..=last_segment {
+            let segment_path = segment_path(path, number);
+            let file = match File::open(&segment_path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound && number > 0 => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Segment {} is missing but segment {} exists",
+                            segment_path.display(),
+                            last_segment
+                        ),
+                    ));
+                }
+                Err(e) => return Err(e),
+            };
+            let len = file.metadata()?.len();
+            segments.push(Segment {
+                path: segment_path,
+                file,
+                len,
+            });
+        }
+
+        for segment in &segments[..segments.len() - 1] {
+            if segment.len != segment_bytes {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Segment {} has {} bytes but is followed by another segment; expected {}",
+                        segment.path.display(),
+                        segment.len,
+                        segment_bytes
+                    ),
+                ));
+            }
+        }
+        if let Some(last) = segments.last() {
+            if last.len > segment_bytes {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Segment {} has {} bytes, more than the segment size {}",
+                        last.path.display(),
+                        last.len,
+                        segment_bytes
+                    ),
+                ));
+            }
+        }
+
+        Ok(SegmentedFile {
+            segments,
+            segment_bytes,
+            position: 0,
+        })
+    }
+
+    /// Paths of the segments, in order.
+    pub fn segment_paths(&self) -> impl Iterator<Item = &Path> {
+        self.segments.iter().map(|segment| segment.path.as_path())
+    }
+
+    /// Total length of all segments in bytes.
+    pub fn len(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maps a relation block to its segment number and byte offset within that segment.
+    pub fn locate(&self, block: BlockNumber, block_size: usize) -> (u32, u64) {
+        let position = *block as u64 * block_size as u64;
+        (
+            (position / self.segment_bytes) as u32,
+            position % self.segment_bytes,
+        )
+    }
+}
+
+impl Read for SegmentedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let index = (self.position / self.segment_bytes) as usize;
+        let offset = self.position % self.segment_bytes;
+        let Some(segment) = self.segments.get_mut(index) else {
+            return Ok(0);
+        };
+        if offset >= segment.len {
+            return Ok(0);
+        }
+
+        let available = (segment.len - offset).min(buf.len() as u64) as usize;
+        segment.file.seek(SeekFrom::Start(offset))?;
+        let read = segment.file.read(&mut buf[..available])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for SegmentedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        match target {
+            Some(target) => {
+                self.position = target;
+                Ok(target)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek to a negative position",
+            )),
+        }
+    }
+}
+
+/// Path of segment `number` of the relation whose first segment is `path`.
+pub fn segment_path(path: &Path, number: u32) -> PathBuf {
+    if number == 0 {
+        return path.to_path_buf();
+    }
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", number));
+    path.with_file_name(name)
+}
+
+/// Highest `N` for which a `path.N` file exists next to `path`, or zero.
+fn last_segment_number(path: &Path) -> io::Result<u32> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let Some(base_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(0);
+    };
+    let prefix = format!("{}.", base_name);
+
+    let mut last = 0;
+    for entry in fs::read_dir(directory)? {
+        let name = entry?.file_name();
+        if let Some(number) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|suffix| suffix.parse::<u32>().ok())
+        {
+            last = last.max(number);
+        }
+    }
+    Ok(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: usize = 1024;
+
+    fn write_blocks(path: &Path, first: u8, count: u8) {
+        let data: Vec<u8> = (first..first + count)
+            .flat_map(|block| vec![block; BLOCK])
+            .collect();
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn chains_segments_by_global_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("16384");
+        write_blocks(&base, 0, 2);
+        write_blocks(&segment_path(&base, 1), 2, 2);
+        write_blocks(&segment_path(&base, 2), 4, 1);
+
+        let mut file = SegmentedFile::open(&base, BLOCK, 2).unwrap();
+        assert_eq!(file.len(), 5 * BLOCK as u64);
+        assert_eq!(file.locate(BlockNumber(3), BLOCK), (1, BLOCK as u64));
+
+        let mut block = vec![0u8; BLOCK];
+        for expected in [3u8, 4, 0] {
+            file.seek(SeekFrom::Start(expected as u64 * BLOCK as u64))
+                .unwrap();
+            file.read_exact(&mut block).unwrap();
+            assert!(block.iter().all(|&byte| byte == expected));
+        }
+    }
+
+    #[test]
+    fn reports_missing_and_short_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("16384");
+        write_blocks(&base, 0, 2);
+        write_blocks(&segment_path(&base, 2), 4, 1);
+        assert!(SegmentedFile::open(&base, BLOCK, 2).is_err());
+
+        write_blocks(&segment_path(&base, 1), 2, 1);
+        assert!(SegmentedFile::open(&base, BLOCK, 2).is_err());
+
+        write_blocks(&segment_path(&base, 1), 2, 2);
+        assert!(SegmentedFile::open(&base, BLOCK, 2).is_ok());
+    }
+}
@@ -0,0 +1,168 @@
+//! Relation mapper files, `pg_filenode.map` (`relmapper.c`).
+//!
+//! A few catalogs, `pg_class` among them, cannot record their own filenode in
+//! `pg_class`: their `relfilenode` is zero and the filenode comes from the map
+//! in `global` for shared catalogs, or in the database directory for the rest.
+//! The file is a raw `RelMapFile` struct followed by a CRC-32C over it.
+
+use crate::crc::crc32c;
+use crate::{read_u32, Endianness};
+use std::io;
+use std::path::Path;
+
+/// Magic number opening the file (`RELMAPPER_FILEMAGIC`).
+pub const RELMAPPER_FILEMAGIC: u32 = 0x0059_2717;
+
+/// Name of the map file in `global` and each database directory.
+pub const RELMAPPER_FILENAME: &str = "pg_filenode.map";
+
+/// Size of one mapping: the relation OID and its filenode.
+const MAPPING_SIZE: usize = 8;
+/// Magic and mapping count ahead of the mappings.
+const HEADER_SIZE: usize = 8;
+
+/// Slots for mappings by file size: 62 padded to 512 bytes before PostgreSQL 16,
+/// 64 without padding since.
+const LAYOUTS: [(usize, usize); 2] = [(512, 62), (524, 64)];
+
+/// One relation and the filenode it is stored in (`RelMapping`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelMapping {
+    mapoid: u32,
+    mapfilenumber: u32,
+}
+
+impl RelMapping {
+    pub fn mapoid(&self) -> u32 {
+        self.mapoid
+    }
+
+    pub fn mapfilenumber(&self) -> u32 {
+        self.mapfilenumber
+    }
+}
+
+/// The contents of a `pg_filenode.map` file (`RelMapFile`).
+#[derive(Debug, Clone)]
+pub struct RelMapFile {
+    mappings: Vec<RelMapping>,
+    /// CRC-32C stored in the file.
+    crc: u32,
+    /// CRC-32C of the bytes the stored one covers.
+    computed_crc: u32,
+}
+
+impl RelMapFile {
+    /// Parses a map file written in `endianness`.
+    ///
+    /// A wrong size, magic number or mapping count is an error; a CRC mismatch
+    /// is not, see [`RelMapFile::crc_is_valid`].
+    pub fn from_bytes(bytes: &[u8], endianness: Endianness) -> io::Result<RelMapFile> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let max_mappings = LAYOUTS
+            .iter()
+            .find(|&&(size, _)| size == bytes.len())
+            .map(|&(_, max_mappings)| max_mappings)
+            .ok_or_else(|| invalid(format!("Relation map file has size {}", bytes.len())))?;
+
+        let mut reader = bytes;
+        let magic = read_u32(&mut reader, endianness)?;
+        if magic != RELMAPPER_FILEMAGIC {
+            return Err(invalid(format!(
+                "Relation map file contains invalid magic number {:#X}",
+                magic
+            )));
+        }
+        let num_mappings = read_u32(&mut reader, endianness)? as usize;
+        if num_mappings > max_mappings {
+            return Err(invalid(format!(
+                "Relation map file contains {} mappings, at most {} fit",
+                num_mappings, max_mappings
+            )));
+        }
+        let mut mappings = Vec::with_capacity(num_mappings);
+        for _ in 0..num_mappings {
+            mappings.push(RelMapping {
+                mapoid: read_u32(&mut reader, endianness)?,
+                mapfilenumber: read_u32(&mut reader, endianness)?,
+            });
+        }
+
+        let crc_offset = HEADER_SIZE + max_mappings * MAPPING_SIZE;
+        let crc = read_u32(&mut &bytes[crc_offset..], endianness)?;
+        Ok(RelMapFile {
+            mappings,
+            crc,
+            computed_crc: crc32c(&bytes[..crc_offset]),
+        })
+    }
+
+    /// Reads a map file, or the `pg_filenode.map` inside a directory.
+    pub fn from_path(path: &Path, endianness: Endianness) -> io::Result<RelMapFile> {
+        let path = if path.is_dir() {
+            path.join(RELMAPPER_FILENAME)
+        } else {
+            path.to_path_buf()
+        };
+        let bytes = std::fs::read(&path)?;
+        RelMapFile::from_bytes(&bytes, endianness)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+    }
+
+    pub fn mappings(&self) -> &[RelMapping] {
+        &self.mappings
+    }
+
+    /// The filenode of the mapped relation `relid` (`RelationMapOidToFilenumber`).
+    pub fn filenode(&self, relid: u32) -> Option<u32> {
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.mapoid == relid)
+            .map(|mapping| mapping.mapfilenumber)
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    pub fn computed_crc(&self) -> u32 {
+        self.computed_crc
+    }
+
+    pub fn crc_is_valid(&self) -> bool {
+        self.crc == self.computed_crc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_both_sizes() {
+        for (size, max_mappings) in LAYOUTS {
+            let mut bytes = vec![0u8; size];
+            bytes[0..4].copy_from_slice(&RELMAPPER_FILEMAGIC.to_le_bytes());
+            bytes[4..8].copy_from_slice(&2u32.to_le_bytes());
+            for (slot, (oid, filenode)) in [(1259u32, 1259u32), (1249, 16500)].iter().enumerate() {
+                let offset = HEADER_SIZE + slot * MAPPING_SIZE;
+                bytes[offset..offset + 4].copy_from_slice(&oid.to_le_bytes());
+                bytes[offset + 4..offset + 8].copy_from_slice(&filenode.to_le_bytes());
+            }
+            let crc_offset = HEADER_SIZE + max_mappings * MAPPING_SIZE;
+            let crc = crc32c(&bytes[..crc_offset]);
+            bytes[crc_offset..crc_offset + 4].copy_from_slice(&crc.to_le_bytes());
+
+            let map = RelMapFile::from_bytes(&bytes, Endianness::LittleEndian).unwrap();
+            assert!(map.crc_is_valid());
+            assert_eq!(map.filenode(1249), Some(16500));
+            assert_eq!(map.filenode(1247), None);
+
+            bytes[12] ^= 1;
+            let map = RelMapFile::from_bytes(&bytes, Endianness::LittleEndian).unwrap();
+            assert!(!map.crc_is_valid());
+            bytes[0] = 0;
+            assert!(RelMapFile::from_bytes(&bytes, Endianness::LittleEndian).is_err());
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Simple LRU (SLRU) files: `pg_xact`, `pg_subtrans`, `pg_multixact` and friends (`slru.c`).
+//!
+//! An SLRU is an array of `BLCKSZ` pages split across segment files of
+//! `SLRU_PAGES_PER_SEGMENT` pages each, named after the segment number in hex.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Number of pages in one SLRU segment file.
+pub const SLRU_PAGES_PER_SEGMENT: u32 = 32;
+
+/// Reads pages of one SLRU directory, caching those already read.
+pub struct SlruReader {
+    dir: PathBuf,
+    block_size: usize,
+    pages: RefCell<HashMap<u32, Vec<u8>>>,
+}
+
+impl SlruReader {
+    pub fn new(dir: &Path, block_size: usize) -> SlruReader {
+        SlruReader {
+            dir: dir.to_path_buf(),
+            block_size,
+            pages: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Path of the segment file holding `pageno`.
+    pub fn segment_path(&self, pageno: u32) -> PathBuf {
+        self.dir
+            .join(format!("{:04X}", pageno / SLRU_PAGES_PER_SEGMENT))
+    }
+
+    /// Reads page `pageno` and passes it to `f`.
+    ///
+    /// A missing segment file is an error. A page past the end of an existing segment
+    /// has not been written out yet and reads as zeros, as a freshly extended page would.
+    pub fn with_page<T>(&self, pageno: u32, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        if let Some(page) = self.pages.borrow().get(&pageno) {
+            return Ok(f(page));
+        }
+
+        let path = self.segment_path(pageno);
+        let mut file = File::open(&path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Cannot open SLRU segment {}: {}", path.display(), err),
+            )
+        })?;
+        let offset = (pageno % SLRU_PAGES_PER_SEGMENT) as u64 * self.block_size as u64;
+        let mut page = vec![0u8; self.block_size];
+        if offset < file.metadata()?.len() {
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut page).map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!(
+                        "Cannot read page {} of SLRU segment {}: {}",
+                        pageno,
+                        path.display(),
+                        err
+                    ),
+                )
+            })?;
+        }
+
+        let result = f(&page);
+        self.pages.borrow_mut().insert(pageno, page);
+        Ok(result)
+    }
+}
@@ -0,0 +1,102 @@
+//! Subtransaction parents from `pg_subtrans` (`subtrans.c`).
+//!
+//! Each xid has a four-byte slot holding the xid of its parent transaction, or
+//! zero for a top-level one. The file is only kept for xids newer than the oldest
+//! running transaction and is cleared when the server starts.
+
+use crate::slru::SlruReader;
+use crate::{read_u32, Endianness, TransactionId};
+use std::io;
+use std::path::Path;
+
+/// Size of one parent slot.
+const SIZE_OF_PARENT: usize = 4;
+
+/// Reads subtransaction parents from a `pg_subtrans` directory.
+pub struct SubtransReader {
+    slru: SlruReader,
+    endianness: Endianness,
+}
+
+impl SubtransReader {
+    /// Reads the `pg_subtrans` directory of a data directory.
+    pub fn open(
+        data_directory: &Path,
+        block_size: usize,
+        endianness: Endianness,
+    ) -> SubtransReader {
+        SubtransReader {
+            slru: SlruReader::new(&data_directory.join("pg_subtrans"), block_size),
+            endianness,
+        }
+    }
+
+    fn xacts_per_page(&self) -> u32 {
+        (self.slru.block_size() / SIZE_OF_PARENT) as u32
+    }
+
+    /// The parent of `xid`, or `None` if it is a top-level transaction (`SubTransGetParent`).
+    pub fn parent(&self, xid: TransactionId) -> io::Result<Option<TransactionId>> {
+        if !xid.is_normal() {
+            return Ok(None);
+        }
+        let entry = (xid.0 % self.xacts_per_page()) as usize * SIZE_OF_PARENT;
+        let endianness = self.endianness;
+        let parent = self
+            .slru
+            .with_page(xid.0 / self.xacts_per_page(), |page| {
+                read_u32(&mut &page[entry..], endianness)
+            })??;
+        Ok(Some(TransactionId(parent)).filter(|parent| parent.is_normal()))
+    }
+
+    /// The parent of `xid`, its parent and so on up to the top-level transaction.
+    pub fn ancestors(&self, xid: TransactionId) -> io::Result<Vec<TransactionId>> {
+        let mut ancestors = Vec::new();
+        let mut current = xid;
+        while let Some(parent) = self.parent(current)? {
+            // Parents are always older; anything else is a stale or corrupt slot.
+            if !parent.precedes(current) {
+                break;
+            }
+            ancestors.push(parent);
+            current = parent;
+        }
+        Ok(ancestors)
+    }
+
+    /// The top-level transaction `xid` belongs to, which is `xid` itself for a
+    /// top-level one (`SubTransGetTopmostTransaction`).
+    pub fn topmost(&self, xid: TransactionId) -> io::Result<TransactionId> {
+        Ok(self.ancestors(xid)?.last().copied().unwrap_or(xid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_parent_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let block_size = 8192;
+        std::fs::create_dir(dir.path().join("pg_subtrans")).unwrap();
+
+        // 702 is a child of 701, itself a child of 700.
+        let mut page = vec![0u8; block_size];
+        page[701 * 4..702 * 4].copy_from_slice(&700u32.to_le_bytes());
+        page[702 * 4..703 * 4].copy_from_slice(&701u32.to_le_bytes());
+        std::fs::write(dir.path().join("pg_subtrans").join("0000"), &page).unwrap();
+
+        let subtrans = SubtransReader::open(dir.path(), block_size, Endianness::LittleEndian);
+        assert_eq!(
+            subtrans.parent(TransactionId(702)).unwrap(),
+            Some(TransactionId(701))
+        );
+        assert_eq!(subtrans.parent(TransactionId(700)).unwrap(), None);
+        assert_eq!(
+            subtrans.topmost(TransactionId(702)).unwrap(),
+            TransactionId(700)
+        );
+    }
+}
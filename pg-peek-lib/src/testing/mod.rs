@@ -0,0 +1,120 @@
+//! Builders for the tuples and pages that tests feed to the readers.
+//!
+//! Everything is laid out as a little-endian build with 8 KiB blocks writes it.
+
+use crate::catalog::DEFAULT_NAME_DATA_LEN;
+use crate::{Infomask, SIZE_OF_ITEM_ID_DATA, SIZE_OF_PAGE_HEADER_DATA};
+use std::fs;
+use std::path::Path;
+
+/// Block size of the pages built here.
+pub const BLCKSZ: usize = 8192;
+
+/// A column value, laid out at its type's alignment.
+#[derive(Debug, Clone, Copy)]
+pub enum Field<'a> {
+    Oid(u32),
+    Name(&'a str),
+    Int2(i16),
+    Int4(i32),
+    Float4(f32),
+    Bool(bool),
+    Char(u8),
+    /// A varlena with a four-byte header, such as `bytea`.
+    Varlena(&'a [u8]),
+}
+
+/// User data holding `fields`, laid out as `heap_fill_tuple` would.
+pub fn fill(fields: &[Field]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for field in fields {
+        let (align, bytes) = match *field {
+            Field::Oid(value) => (4, value.to_le_bytes().to_vec()),
+            Field::Name(value) => {
+                let mut bytes = value.as_bytes().to_vec();
+                bytes.resize(DEFAULT_NAME_DATA_LEN as usize, 0);
+                (1, bytes)
+            }
+            Field::Int2(value) => (2, value.to_le_bytes().to_vec()),
+            Field::Int4(value) => (4, value.to_le_bytes().to_vec()),
+            Field::Float4(value) => (4, value.to_le_bytes().to_vec()),
+            Field::Bool(value) => (1, vec![value as u8]),
+            Field::Char(value) => (1, vec![value]),
+            Field::Varlena(value) => {
+                let mut bytes = (((value.len() + 4) as u32) << 2).to_le_bytes().to_vec();
+                bytes.extend_from_slice(value);
+                (4, bytes)
+            }
+        };
+        data.resize(data.len().next_multiple_of(align), 0);
+        data.extend(bytes);
+    }
+    data
+}
+
+/// A tuple header for `natts` attributes and no nulls, followed by `data`.
+pub fn heap_tuple(xmin: u32, xmax: u32, infomask: Infomask, natts: u16, data: &[u8]) -> Vec<u8> {
+    let mut tuple = vec![0u8; 24];
+    tuple[0..4].copy_from_slice(&xmin.to_le_bytes());
+    tuple[4..8].copy_from_slice(&xmax.to_le_bytes());
+    tuple[18..20].copy_from_slice(&natts.to_le_bytes());
+    tuple[20..22].copy_from_slice(&infomask.bits().to_le_bytes());
+    tuple[22] = 24;
+    tuple.extend_from_slice(data);
+    tuple
+}
+
+/// A tuple holding `fields`, inserted by the frozen transaction and visible to everyone.
+pub fn tuple(fields: &[Field]) -> Vec<u8> {
+    heap_tuple(
+        2,
+        0,
+        Infomask::HEAP_XMIN_COMMITTED,
+        fields.len() as u16,
+        &fill(fields),
+    )
+}
+
+/// A page holding `items` at the given `lp_off`s, in line pointer order.
+///
+/// An item running past the end of the page is cut short.
+pub fn page_with_items(items: &[(u16, &[u8])]) -> Vec<u8> {
+    let mut page = vec![0u8; BLCKSZ];
+    let pd_lower = SIZE_OF_PAGE_HEADER_DATA + items.len() * SIZE_OF_ITEM_ID_DATA;
+    let pd_upper = items
+        .iter()
+        .map(|&(lp_off, _)| lp_off)
+        .min()
+        .unwrap_or(BLCKSZ as u16);
+    page[12..14].copy_from_slice(&(pd_lower as u16).to_le_bytes());
+    page[14..16].copy_from_slice(&pd_upper.to_le_bytes());
+    page[16..18].copy_from_slice(&(BLCKSZ as u16).to_le_bytes());
+    page[18..20].copy_from_slice(&(BLCKSZ as u16 | 4).to_le_bytes());
+    for (index, &(lp_off, tuple)) in items.iter().enumerate() {
+        let word = lp_off as u32 | 1 << 15 | (tuple.len() as u32) << 17;
+        let at = SIZE_OF_PAGE_HEADER_DATA + index * SIZE_OF_ITEM_ID_DATA;
+        page[at..at + 4].copy_from_slice(&word.to_le_bytes());
+        let start = lp_off as usize;
+        let end = (start + tuple.len()).min(page.len());
+        page[start..end].copy_from_slice(&tuple[..end - start]);
+    }
+    page
+}
+
+/// A page holding `tuples`, stacked down from its end as `PageAddItem` places them.
+pub fn page(tuples: &[Vec<u8>]) -> Vec<u8> {
+    let mut upper = BLCKSZ;
+    let items: Vec<(u16, &[u8])> = tuples
+        .iter()
+        .map(|tuple| {
+            upper = (upper - tuple.len()) & !7;
+            (upper as u16, &tuple[..])
+        })
+        .collect();
+    page_with_items(&items)
+}
+
+/// Writes a one-page relation holding `tuples` to `path`.
+pub fn write_relation(path: &Path, tuples: &[Vec<u8>]) {
+    fs::write(path, page(tuples)).unwrap();
+}
@@ -0,0 +1,373 @@
+//! Resolution of out-of-line TOAST values (`heaptoast.c`, `detoast.c`).
+//!
+//! A value too large for its tuple is split into chunks of at most
+//! `TOAST_MAX_CHUNK_SIZE` bytes stored as rows `(chunk_id oid, chunk_seq int4,
+//! chunk_data bytea)` of the table's `pg_toast_<oid>` relation. The tuple keeps
+//! a `varatt_external` pointer naming the TOAST relation and `chunk_id`.
+
+use crate::datum::{decode_varlena_payload, oid, Attribute, Datum, TupleDescriptor};
+use crate::relation::segment::SegmentedFile;
+use crate::relation::RelationReader;
+use crate::types::TypeAlign;
+use crate::varlena::{decompress, VarattExternal, VARHDRSZ};
+use crate::visibility::{toast_chunk_visible, TransactionOracle, TransactionStatus};
+use crate::{BlockNumber, Endianness, PageItem, TransactionId};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where one chunk of a value is stored.
+#[derive(Debug, Clone, Copy)]
+struct ChunkLocation {
+    chunk_seq: i32,
+    block: BlockNumber,
+    /// Zero-based index of the item on its page.
+    item: usize,
+}
+
+/// An open TOAST relation and the chunks it holds, by `chunk_id`.
+struct ToastRelation {
+    reader: RelationReader<SegmentedFile>,
+    chunks: HashMap<u32, Vec<ChunkLocation>>,
+}
+
+/// Fetches out-of-line values from the TOAST relations of one database directory.
+///
+/// Each TOAST relation is scanned once, on first use, to find where its chunks live.
+/// Chunks left behind by aborted insertions are skipped.
+pub struct ToastReader {
+    database_dir: PathBuf,
+    block_size: Option<usize>,
+    relseg_size: Option<u32>,
+    endianness: Endianness,
+    filenodes: HashMap<u32, u32>,
+    relations: HashMap<u32, ToastRelation>,
+    oracle: Box<dyn TransactionOracle>,
+}
+
+/// Descriptor of a TOAST relation's rows.
+fn chunk_descriptor() -> TupleDescriptor {
+    TupleDescriptor::new(vec![
+        Attribute::new(oid::OIDOID, 4, true, TypeAlign::Int),
+        Attribute::new(oid::INT4OID, 4, true, TypeAlign::Int),
+        Attribute::new(oid::BYTEAOID, -1, false, TypeAlign::Int),
+    ])
+}
+
+fn corrupt(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl ToastReader {
+    /// Reads TOAST relations from `database_dir` (e.g. `base/16384`).
+    ///
+    /// TOAST relations are assumed to be stored under a file named after their OID;
+    /// use [`ToastReader::set_filenode`] for any that were rewritten since.
+    pub fn new(
+        database_dir: &Path,
+        block_size: Option<usize>,
+        relseg_size: Option<u32>,
+        endianness: Endianness,
+    ) -> ToastReader {
+        ToastReader {
+            database_dir: database_dir.to_path_buf(),
+            block_size,
+            relseg_size,
+            endianness,
+            filenodes: HashMap::new(),
+            relations: HashMap::new(),
+            // Without `pg_xact`, only hint bits tell chunks of aborted insertions apart.
+            oracle: Box::new(|_: TransactionId| Ok(TransactionStatus::Committed)),
+        }
+    }
+
+    /// Looks up the inserters of chunks whose hint bits are not set in `oracle`.
+    pub fn set_oracle(&mut self, oracle: Box<dyn TransactionOracle>) {
+        self.oracle = oracle;
+        self.relations.clear();
+    }
+
+    /// Records that the TOAST relation `toast_relid` is stored in `relfilenode`.
+    pub fn set_filenode(&mut self, toast_relid: u32, relfilenode: u32) {
+        self.filenodes.insert(toast_relid, relfilenode);
+        self.relations.remove(&toast_relid);
+    }
+
+    /// Path of the first segment of the TOAST relation `toast_relid`.
+    pub fn relation_path(&self, toast_relid: u32) -> PathBuf {
+        let filenode = self
+            .filenodes
+            .get(&toast_relid)
+            .copied()
+            .unwrap_or(toast_relid);
+        self.database_dir.join(filenode.to_string())
+    }
+
+    /// Fetches the value `pointer` refers to, decompressed and without a varlena header.
+    pub fn fetch(&mut self, pointer: &VarattExternal) -> io::Result<Vec<u8>> {
+        let endianness = self.endianness;
+        let descriptor = chunk_descriptor();
+        let relation = self.relation(pointer.va_toastrelid())?;
+        let locations = relation
+            .chunks
+            .get(&pointer.va_valueid())
+            .cloned()
+            .unwrap_or_default();
+
+        let extsize = pointer.extsize() as usize;
+        let mut data = Vec::with_capacity(extsize);
+        for (expected, location) in locations.iter().enumerate() {
+            if expected > 0 && locations[expected - 1].chunk_seq == location.chunk_seq {
+                return Err(corrupt(format!(
+                    "Duplicate chunk number {} for toast value {} in {}",
+                    location.chunk_seq,
+                    pointer.va_valueid(),
+                    pointer.va_toastrelid()
+                )));
+            }
+            if location.chunk_seq as usize != expected {
+                return Err(corrupt(format!(
+                    "Unexpected chunk number {} (expected {}) for toast value {} in {}",
+                    location.chunk_seq,
+                    expected,
+                    pointer.va_valueid(),
+                    pointer.va_toastrelid()
+                )));
+            }
+            let page = relation.reader.read_block(location.block)?;
+            let Some(PageItem::Normal(tuple)) = page.items().get(location.item) else {
+                return Err(corrupt(format!(
+                    "Chunk {} of toast value {} is no longer at ({},{})",
+                    location.chunk_seq,
+                    pointer.va_valueid(),
+                    *location.block,
+                    location.item + 1
+                )));
+            };
+            match &tuple.decode(&descriptor, endianness)?[2] {
+                Datum::Bytea(chunk) => data.extend_from_slice(chunk),
+                _ => {
+                    return Err(corrupt(format!(
+                        "Chunk {} of toast value {} has no data",
+                        location.chunk_seq,
+                        pointer.va_valueid()
+                    )))
+                }
+            }
+        }
+
+        if data.len() != extsize {
+            return Err(corrupt(format!(
+                "Toast value {} in {} has {} bytes in {} chunks, expected {}",
+                pointer.va_valueid(),
+                pointer.va_toastrelid(),
+                data.len(),
+                locations.len(),
+                extsize
+            )));
+        }
+
+        match pointer.compression_method() {
+            // Compressed data keeps its `va_tcinfo` word ahead of the compressed bytes.
+            Some(method) => decompress(
+                data.get(VARHDRSZ..).unwrap_or_default(),
+                pointer.raw_size() as usize,
+                method,
+            ),
+            None => Ok(data),
+        }
+    }
+
+    /// Replaces a [`Datum::External`] with the value it points to; other values pass through.
+    pub fn detoast(&mut self, attribute: &Attribute, datum: Datum) -> io::Result<Datum> {
+        match datum {
+            Datum::External(pointer) => {
+                let payload = self.fetch(&pointer)?;
+                decode_varlena_payload(attribute, &payload, self.endianness)
+            }
+            datum => Ok(datum),
+        }
+    }
+
+    /// Replaces every [`Datum::External`] in a decoded tuple with the value it points to.
+    pub fn detoast_tuple(
+        &mut self,
+        descriptor: &TupleDescriptor,
+        values: Vec<Datum>,
+    ) -> io::Result<Vec<Datum>> {
+        descriptor
+            .attributes()
+            .iter()
+            .zip(values)
+            .map(|(attribute, datum)| self.detoast(attribute, datum))
+            .collect()
+    }
+
+    fn relation(&mut self, toast_relid: u32) -> io::Result<&mut ToastRelation> {
+        if !self.relations.contains_key(&toast_relid) {
+            let relation = self.scan(toast_relid)?;
+            self.relations.insert(toast_relid, relation);
+        }
+        Ok(self.relations.get_mut(&toast_relid).unwrap())
+    }
+
+    /// Opens a TOAST relation and indexes its chunks by `chunk_id`, sorted by `chunk_seq`.
+    fn scan(&self, toast_relid: u32) -> io::Result<ToastRelation> {
+        let path = self.relation_path(toast_relid);
+        let mut reader = RelationReader::open_segments(
+            &path,
+            self.block_size,
+            self.relseg_size,
+            self.endianness,
+        )
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Cannot open toast relation {}: {}", path.display(), err),
+            )
+        })?;
+
+        // Only the fixed-width key columns are needed to place a chunk.
+        let key = TupleDescriptor::new(chunk_descriptor().attributes()[..2].to_vec());
+        let mut chunks: HashMap<u32, Vec<ChunkLocation>> = HashMap::new();
+        for page in reader.pages() {
+            let page = page?;
+            for (item, page_item) in page.items().iter().enumerate() {
+                let PageItem::Normal(tuple) = page_item else {
+                    continue;
+                };
+                if !toast_chunk_visible(tuple.header(), self.oracle.as_ref())? {
+                    continue;
+                }
+                let values = tuple.decode(&key, self.endianness)?;
+                if let [Datum::Oid(chunk_id), Datum::Int4(chunk_seq)] = values[..] {
+                    chunks.entry(chunk_id).or_default().push(ChunkLocation {
+                        chunk_seq,
+                        block: page.block_number(),
+                        item,
+                    });
+                }
+            }
+        }
+        for locations in chunks.values_mut() {
+            locations.sort_by_key(|location| location.chunk_seq);
+        }
+
+        Ok(ToastRelation { reader, chunks })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fill, heap_tuple, page, Field};
+    use crate::varlena::{Varlena, VarlenaHeader};
+    use crate::Infomask;
+
+    const TOAST_RELID: u32 = 16400;
+
+    /// A chunk row inserted by `xmin`, with a four-byte varlena header on its data.
+    fn chunk(xmin: u32, infomask: Infomask, chunk_id: u32, chunk_seq: i32, data: &[u8]) -> Vec<u8> {
+        let fields = [
+            Field::Oid(chunk_id),
+            Field::Int4(chunk_seq),
+            Field::Varlena(data),
+        ];
+        heap_tuple(xmin, 0, infomask, 3, &fill(&fields))
+    }
+
+    /// A TOAST relation of `pages` in a scratch database directory.
+    fn toast_reader(pages: &[Vec<Vec<u8>>]) -> (tempfile::TempDir, ToastReader) {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = pages.iter().flat_map(|tuples| page(tuples)).collect();
+        std::fs::write(dir.path().join(TOAST_RELID.to_string()), data).unwrap();
+        let reader = ToastReader::new(dir.path(), None, None, Endianness::LittleEndian);
+        (dir, reader)
+    }
+
+    fn pointer(rawsize: u32, extinfo: u32, valueid: u32) -> VarattExternal {
+        let mut bytes = vec![0x01, 18];
+        for field in [rawsize, extinfo, valueid, TOAST_RELID] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        match Varlena::parse(&bytes, Endianness::LittleEndian)
+            .unwrap()
+            .header()
+        {
+            VarlenaHeader::External(external) => external,
+            header => panic!("expected an external pointer, got {:?}", header),
+        }
+    }
+
+    #[test]
+    fn reassembles_chunks_in_order() {
+        let none = Infomask::empty();
+        // Value 1000 is plain text in three chunks, stored out of order over two pages.
+        // Value 2000 is "abcabcabcabc" compressed with pglz behind its tcinfo word,
+        // split mid-way through the compressed bytes.
+        let mut compressed = 12u32.to_le_bytes().to_vec();
+        compressed.extend_from_slice(&[0b0000_1000, b'a', b'b', b'c', 0x06, 0x03]);
+        let (_dir, mut reader) = toast_reader(&[
+            vec![
+                chunk(100, none, 1000, 2, b"ghi"),
+                chunk(100, none, 2000, 1, &compressed[6..]),
+                chunk(100, none, 1000, 0, b"abc"),
+            ],
+            vec![
+                chunk(100, none, 2000, 0, &compressed[..6]),
+                chunk(100, none, 1000, 1, b"def"),
+            ],
+        ]);
+
+        assert_eq!(reader.fetch(&pointer(13, 9, 1000)).unwrap(), b"abcdefghi");
+        assert_eq!(
+            reader.fetch(&pointer(16, 10, 2000)).unwrap(),
+            b"abcabcabcabc"
+        );
+
+        let descriptor = TupleDescriptor::new(vec![
+            Attribute::new(oid::INT4OID, 4, true, TypeAlign::Int),
+            Attribute::new(oid::TEXTOID, -1, false, TypeAlign::Int),
+        ]);
+        let values = vec![Datum::Int4(1), Datum::External(pointer(13, 9, 1000))];
+        assert_eq!(
+            reader.detoast_tuple(&descriptor, values).unwrap(),
+            [Datum::Int4(1), Datum::Text("abcdefghi".to_string())]
+        );
+    }
+
+    #[test]
+    fn rejects_missing_and_duplicate_chunks() {
+        let none = Infomask::empty();
+        let pages = [vec![
+            chunk(100, none, 1000, 0, b"abc"),
+            chunk(100, none, 1000, 2, b"ghi"),
+            chunk(100, none, 2000, 0, b"abc"),
+            chunk(100, none, 3000, 0, b"abc"),
+            chunk(200, none, 3000, 0, b"xyz"),
+            chunk(300, Infomask::HEAP_XMIN_INVALID, 3000, 0, b"xyz"),
+        ]];
+        let (_dir, mut reader) = toast_reader(&pages);
+        // Chunk 1 is missing.
+        assert!(reader.fetch(&pointer(13, 9, 1000)).is_err());
+        // The pointer promises more bytes than the chunks hold.
+        assert!(reader.fetch(&pointer(8, 4, 2000)).is_err());
+        assert_eq!(reader.fetch(&pointer(7, 3, 2000)).unwrap(), b"abc");
+        // Only the hint bit tells the third copy of chunk 0 apart.
+        let err = reader.fetch(&pointer(7, 3, 3000)).unwrap_err();
+        assert!(
+            err.to_string().starts_with("Duplicate chunk number 0"),
+            "{}",
+            err
+        );
+
+        // Transaction 200 aborted, leaving one copy of each chunk.
+        reader.set_oracle(Box::new(|xid: TransactionId| {
+            Ok(match *xid {
+                200 => TransactionStatus::Aborted,
+                _ => TransactionStatus::Committed,
+            })
+        }));
+        assert_eq!(reader.fetch(&pointer(7, 3, 3000)).unwrap(), b"abc");
+    }
+}
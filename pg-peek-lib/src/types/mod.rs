@@ -4,15 +4,15 @@ use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use serde_json;
 
-use std::io::{BufRead};
 use std::ops::Deref;
 use std::str::FromStr;
 use strum_macros::{Display, EnumString};
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Oid(u32);
 
+/// A function reference, held by name as `regproc` prints it.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Regproc(u32);
+pub struct Regproc(String);
 
 impl Deref for Oid {
     type Target = u32;
@@ -23,14 +23,14 @@ impl Deref for Oid {
 }
 
 impl Deref for Regproc {
-    type Target = u32;
+    type Target = str;
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
-#[derive(Debug, PartialEq, EnumString, Display, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display, Serialize, Deserialize)]
 pub enum TypeType {
     #[strum(serialize = "b")]
     Base,
@@ -86,7 +86,7 @@ pub enum TypeCategory {
     Internal,
 }
 
-#[derive(Debug, PartialEq, EnumString, Display, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display, Serialize, Deserialize)]
 pub enum TypeAlign {
     #[strum(serialize = "c")]
     Char,
@@ -98,7 +98,7 @@ pub enum TypeAlign {
     Double,
 }
 
-#[derive(Debug, PartialEq, EnumString, Display, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display, Serialize, Deserialize)]
 pub enum TypeStorage {
     #[strum(serialize = "p")]
     Plain,
@@ -110,6 +110,18 @@ pub enum TypeStorage {
     Main,
 }
 
+impl TypeAlign {
+    /// Alignment in bytes on the platforms PostgreSQL supports.
+    pub fn alignment(&self) -> usize {
+        match self {
+            TypeAlign::Char => 1,
+            TypeAlign::Short => 2,
+            TypeAlign::Int => 4,
+            TypeAlign::Double => 8,
+        }
+    }
+}
+
 bitflags! {
     #[derive(Debug, Serialize, Deserialize)]
     pub struct AclMode: u64 {
@@ -144,12 +156,12 @@ pub struct PgType {
     array: Option<Oid>, // If "true" array type exists with this type as element type, links to it
     input: Regproc, // I/O conversion procedures (text format) for the datatype
     output: Regproc,
-    receive: Regproc, // I/O conversion procedures (binary format) for the datatype
-    send: Regproc,
-    mod_in: Regproc, // I/O functions for optional type modifiers
-    mod_out: Regproc,
-    analyze: Regproc,               // Custom ANALYZE procedure for the datatype
-    align: TypeAlign,               // Alignment required when storing a value of this type
+    receive: Option<Regproc>, // I/O conversion procedures (binary format) for the datatype
+    send: Option<Regproc>,
+    mod_in: Option<Regproc>, // I/O functions for optional type modifiers
+    mod_out: Option<Regproc>,
+    analyze: Option<Regproc>, // Custom ANALYZE procedure for the datatype
+    align: TypeAlign,         // Alignment required when storing a value of this type
     storage: TypeStorage, // Specifies if the type is prepared for toasting and the default strategy
     not_null: bool,       // Represents a "NOT NULL" constraint against this datatype
     base_type: Option<Oid>, // If a domain, shows the base (or domain) type it is based on
@@ -161,13 +173,103 @@ pub struct PgType {
     acl: Vec<AclItem>,              // Access permissions
 }
 
-fn json_to_pg_type(json_string: &str) -> Result<PgType, serde_json::Error> {
-    #[derive(Debug, Serialize, Deserialize)]
+impl PgType {
+    pub fn oid(&self) -> u32 {
+        *self.oid
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn namespace(&self) -> u32 {
+        *self.namespace
+    }
+
+    pub fn type_type(&self) -> &TypeType {
+        &self.type_type
+    }
+
+    pub fn category(&self) -> &TypeCategory {
+        &self.category
+    }
+
+    pub fn relation_id(&self) -> Option<u32> {
+        self.relation_id.as_deref().copied()
+    }
+
+    pub fn subscript(&self) -> Option<&str> {
+        self.subscript.as_deref()
+    }
+
+    pub fn element(&self) -> Option<u32> {
+        self.element.as_deref().copied()
+    }
+
+    pub fn array(&self) -> Option<u32> {
+        self.array.as_deref().copied()
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn base_type(&self) -> Option<u32> {
+        self.base_type.as_deref().copied()
+    }
+
+    pub fn type_mod(&self) -> Option<i32> {
+        self.type_mod
+    }
+
+    pub fn collation(&self) -> Option<u32> {
+        self.collation.as_deref().copied()
+    }
+
+    pub fn length(&self) -> i16 {
+        self.length
+    }
+
+    pub fn by_value(&self) -> bool {
+        self.by_value
+    }
+
+    pub fn align(&self) -> TypeAlign {
+        self.align
+    }
+
+    pub fn storage(&self) -> TypeStorage {
+        self.storage
+    }
+}
+
+/// Parses an OID rendered as a string, mapping the invalid OID `"0"` to `None`.
+fn parse_oid(value: &str) -> Result<Option<Oid>, String> {
+    match value.parse::<u32>() {
+        Ok(0) => Ok(None),
+        Ok(oid) => Ok(Some(Oid(oid))),
+        Err(e) => Err(format!("invalid OID {:?}: {}", value, e)),
+    }
+}
+
+/// Parses a `regproc` name, mapping the invalid reference `"-"` to `None`.
+fn parse_regproc(value: String) -> Option<Regproc> {
+    (value != "-").then_some(Regproc(value))
+}
+
+/// Parses the bundled `pg_type.json`: one JSON array of `pg_type` rows as
+/// `row_to_json` renders them, with OIDs as strings and `regproc` columns as names.
+fn json_to_pg_types(json: &str) -> Result<Vec<PgType>, String> {
+    #[derive(Debug, Deserialize)]
     struct Intermediate {
-        oid: u32,
+        oid: String,
         typname: String,
-        typnamespace: u32,
-        typowner: u32,
+        typnamespace: String,
+        typowner: String,
         typlen: i16,
         typbyval: bool,
         typtype: String,
@@ -175,78 +277,142 @@ fn json_to_pg_type(json_string: &str) -> Result<PgType, serde_json::Error> {
         typispreferred: bool,
         typisdefined: bool,
         typdelim: char,
-        typrelid: u32,
-        typarray: u32,
-        typinput: u32,
-        typoutput: u32,
-        typreceive: u32,
-        typsend: u32,
-        typmodin: u32,
-        typmodout: u32,
-        typanalyze: u32,
+        typrelid: String,
+        typsubscript: String,
+        typelem: String,
+        typarray: String,
+        typinput: String,
+        typoutput: String,
+        typreceive: String,
+        typsend: String,
+        typmodin: String,
+        typmodout: String,
+        typanalyze: String,
         typalign: String,
         typstorage: String,
         typnotnull: bool,
-        typbasetype: u32,
+        typbasetype: String,
         typtypmod: i32,
         typndims: i32,
-        typcollation: u32,
-        // assuming typdefaultbin, typdefault, typacl are optional since they're null in the sample
+        typcollation: String,
         typdefaultbin: Option<String>,
         typdefault: Option<String>,
-        typacl: Option<Vec<AclItem>>,
-    }
-
-    let intermediate: Intermediate = serde_json::from_str(json_string)?;
-
-    let pg_type = PgType {
-        oid: Oid(intermediate.oid),
-        name: intermediate.typname,
-        namespace: Oid(intermediate.typnamespace),
-        owner: Oid(intermediate.typowner),
-        length: intermediate.typlen,
-        by_value: intermediate.typbyval,
-        type_type: TypeType::from_str(&intermediate.typtype).unwrap(),
-        category: TypeCategory::from_str(&intermediate.typcategory).unwrap(),
-        is_preferred: intermediate.typispreferred,
-        is_defined: intermediate.typisdefined,
-        delimiter: intermediate.typdelim,
-        relation_id: Some(Oid(intermediate.typrelid)),
-        subscript: None, // There is no mapping field from the JSON
-        element: None,   // Assuming this since there's no "typelem" in the sample
-        array: Some(Oid(intermediate.typarray)),
-        input: Regproc(intermediate.typinput),
-        output: Regproc(intermediate.typoutput),
-        receive: Regproc(intermediate.typreceive),
-        send: Regproc(intermediate.typsend),
-        mod_in: Regproc(intermediate.typmodin),
-        mod_out: Regproc(intermediate.typmodout),
-        analyze: Regproc(intermediate.typanalyze),
-        align: TypeAlign::from_str(&intermediate.typalign).unwrap(),
-        storage: TypeStorage::from_str(&intermediate.typstorage).unwrap(),
-        not_null: intermediate.typnotnull,
-        base_type: Some(Oid(intermediate.typbasetype)),
-        type_mod: Some(intermediate.typtypmod),
-        dimensions: intermediate.typndims,
-        collation: Some(Oid(intermediate.typcollation)),
-        default_binary: intermediate.typdefaultbin,
-        default: intermediate.typdefault,
-        acl: intermediate.typacl.unwrap_or_default(),
-    };
-
-    Ok(pg_type)
+    }
+
+    let rows: Vec<Intermediate> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    rows.into_iter()
+        .map(|row| {
+            let context = |e: String| format!("pg_type {}: {}", row.typname, e);
+            let required_oid = |value: &str| {
+                parse_oid(value)?.ok_or_else(|| format!("unexpected invalid OID {:?}", value))
+            };
+            let enum_value = |value: &str, column: &str| format!("invalid {} {:?}", column, value);
+
+            Ok(PgType {
+                oid: required_oid(&row.oid).map_err(context)?,
+                namespace: required_oid(&row.typnamespace).map_err(context)?,
+                owner: required_oid(&row.typowner).map_err(context)?,
+                length: row.typlen,
+                by_value: row.typbyval,
+                type_type: TypeType::from_str(&row.typtype)
+                    .map_err(|_| context(enum_value(&row.typtype, "typtype")))?,
+                category: TypeCategory::from_str(&row.typcategory)
+                    .map_err(|_| context(enum_value(&row.typcategory, "typcategory")))?,
+                is_preferred: row.typispreferred,
+                is_defined: row.typisdefined,
+                delimiter: row.typdelim,
+                relation_id: parse_oid(&row.typrelid).map_err(context)?,
+                subscript: parse_regproc(row.typsubscript),
+                element: parse_oid(&row.typelem).map_err(context)?,
+                array: parse_oid(&row.typarray).map_err(context)?,
+                input: Regproc(row.typinput),
+                output: Regproc(row.typoutput),
+                receive: parse_regproc(row.typreceive),
+                send: parse_regproc(row.typsend),
+                mod_in: parse_regproc(row.typmodin),
+                mod_out: parse_regproc(row.typmodout),
+                analyze: parse_regproc(row.typanalyze),
+                align: TypeAlign::from_str(&row.typalign)
+                    .map_err(|_| context(enum_value(&row.typalign, "typalign")))?,
+                storage: TypeStorage::from_str(&row.typstorage)
+                    .map_err(|_| context(enum_value(&row.typstorage, "typstorage")))?,
+                not_null: row.typnotnull,
+                base_type: parse_oid(&row.typbasetype).map_err(context)?,
+                type_mod: (row.typtypmod != -1).then_some(row.typtypmod),
+                dimensions: row.typndims,
+                collation: parse_oid(&row.typcollation).map_err(context)?,
+                default_binary: row.typdefaultbin,
+                default: row.typdefault,
+                // typacl renders grantees by role name, which cannot be mapped back to
+                // OIDs here; the bundled catalog has no type ACLs.
+                acl: Vec::new(),
+                name: row.typname,
+            })
+        })
+        .collect()
 }
 
 // lazy load the pg_type data from src/data/pg_type.json with include_str!
 lazy_static! {
-    static ref BOOTSTRAPED_PG_TYPE: Vec<PgType> = {
-        let mut pg_types: Vec<PgType> = Vec::new();
-        let json = include_str!("../data/pg_type.json");
-        let json_lines = json.lines();
-        for line in json_lines {
-            let pg_type = json_to_pg_type(line).unwrap();
-            pg_types.push(pg_type);
-        }
-        pg_types
-    };
+    static ref BOOTSTRAPED_PG_TYPE: Vec<PgType> =
+        json_to_pg_types(include_str!("../data/pg_type.json"))
+            .expect("bundled pg_type.json is valid");
+}
+
+/// OID of the `pg_catalog` namespace.
+pub const PG_CATALOG_NAMESPACE: u32 = 11;
+
+/// Looks up a type in the bundled `pg_type` snapshot by OID.
+pub fn pg_type_by_oid(oid: u32) -> Option<&'static PgType> {
+    BOOTSTRAPED_PG_TYPE
+        .iter()
+        .find(|pg_type| *pg_type.oid == oid)
+}
+
+/// Looks up a type in the bundled `pg_type` snapshot by name, preferring `pg_catalog`
+/// over `information_schema` when both define it.
+pub fn pg_type_by_name(name: &str) -> Option<&'static PgType> {
+    let mut matches = BOOTSTRAPED_PG_TYPE
+        .iter()
+        .filter(|pg_type| pg_type.name == name);
+    let first = matches.next()?;
+    if *first.namespace == PG_CATALOG_NAMESPACE {
+        return Some(first);
+    }
+    matches
+        .find(|pg_type| *pg_type.namespace == PG_CATALOG_NAMESPACE)
+        .or(Some(first))
+}
+
+/// Every type in the bundled `pg_type` snapshot.
+pub fn bootstrap_pg_types() -> &'static [PgType] {
+    &BOOTSTRAPED_PG_TYPE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_bundled_pg_type() {
+        let int4 = pg_type_by_oid(23).unwrap();
+        assert_eq!(int4.name(), "int4");
+        assert_eq!(int4.array(), Some(1007));
+        assert_eq!(int4.receive.as_deref(), Some("int4recv"));
+        assert!(int4.subscript().is_none());
+
+        let int4_array = pg_type_by_name("_int4").unwrap();
+        assert_eq!(int4_array.element(), Some(23));
+        assert_eq!(int4_array.subscript(), Some("array_subscript_handler"));
+
+        // information_schema.cardinal_number is a domain over int4.
+        let cardinal = pg_type_by_name("cardinal_number").unwrap();
+        assert_eq!(*cardinal.type_type(), TypeType::Domain);
+        assert_eq!(cardinal.base_type(), Some(23));
+
+        assert_eq!(
+            pg_type_by_name("bool").unwrap().namespace(),
+            PG_CATALOG_NAMESPACE
+        );
+    }
 }
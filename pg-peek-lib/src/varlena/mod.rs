@@ -0,0 +1,488 @@
+//! Varlena header decoding (`varatt.h`).
+//!
+//! A variable-length value starts with either a one-byte header (short inline
+//! values and TOAST pointers) or a four-byte header (long values, possibly
+//! compressed). The flag bits sit in the lowest bits of the first byte on
+//! little-endian builds and in the highest bits on big-endian builds.
+
+use crate::pglz::pglz_decompress;
+#[cfg(feature = "lz4")]
+use crate::pglz::MAX_ALLOC_SIZE;
+use crate::Endianness;
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+
+/// Size of a four-byte varlena header (`VARHDRSZ`).
+pub const VARHDRSZ: usize = 4;
+/// Size of a one-byte varlena header (`VARHDRSZ_SHORT`).
+pub const VARHDRSZ_SHORT: usize = 1;
+/// Size of the header of an external pointer: the one-byte header plus the tag.
+pub const VARHDRSZ_EXTERNAL: usize = 2;
+/// Size of the compression info word following a compressed value's header.
+pub const VARHDRSZ_COMPRESSED: usize = VARHDRSZ + 4;
+
+/// Mask for the size part of `va_tcinfo` and `va_extinfo` (`VARLENA_EXTSIZE_MASK`).
+const VARLENA_EXTSIZE_MASK: u32 = 0x3FFF_FFFF;
+/// Bits `va_tcinfo` and `va_extinfo` shift the compression method by.
+const VARLENA_EXTSIZE_BITS: u32 = 30;
+
+/// Kind of pointer an external varlena holds (`vartag_external`).
+const VARTAG_INDIRECT: u8 = 1;
+const VARTAG_EXPANDED_RO: u8 = 2;
+const VARTAG_EXPANDED_RW: u8 = 3;
+const VARTAG_ONDISK: u8 = 18;
+
+/// Size of `varatt_external`.
+const SIZE_OF_VARATT_EXTERNAL: usize = 16;
+
+/// Most output bytes a byte of an LZ4 block can yield: each extra match length
+/// byte adds 255.
+#[cfg(feature = "lz4")]
+const LZ4_MAX_EXPANSION: usize = 255;
+
+/// How a compressed value was compressed (`ToastCompressionId`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
+    Pglz,
+    Lz4,
+    /// A method id this crate does not know about.
+    Unknown(u8),
+}
+
+impl fmt::Display for CompressionMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressionMethod::Pglz => write!(f, "pglz"),
+            CompressionMethod::Lz4 => write!(f, "lz4"),
+            CompressionMethod::Unknown(id) => write!(f, "unknown ({})", id),
+        }
+    }
+}
+
+impl CompressionMethod {
+    fn from_id(id: u32) -> CompressionMethod {
+        match id {
+            0 => CompressionMethod::Pglz,
+            1 => CompressionMethod::Lz4,
+            id => CompressionMethod::Unknown(id as u8),
+        }
+    }
+}
+
+/// An on-disk TOAST pointer (`varatt_external`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VarattExternal {
+    /// Original data size, including its varlena header.
+    va_rawsize: i32,
+    /// Size of the stored data and the compression method.
+    va_extinfo: u32,
+    /// Unique ID of the value within the TOAST table.
+    va_valueid: u32,
+    /// OID of the TOAST table holding the value.
+    va_toastrelid: u32,
+}
+
+impl VarattExternal {
+    pub fn va_rawsize(&self) -> i32 {
+        self.va_rawsize
+    }
+
+    pub fn va_valueid(&self) -> u32 {
+        self.va_valueid
+    }
+
+    pub fn va_toastrelid(&self) -> u32 {
+        self.va_toastrelid
+    }
+
+    /// Size of the value as stored in the TOAST table, possibly compressed.
+    pub fn extsize(&self) -> u32 {
+        self.va_extinfo & VARLENA_EXTSIZE_MASK
+    }
+
+    /// Size of the value once decompressed, excluding its varlena header.
+    pub fn raw_size(&self) -> u32 {
+        (self.va_rawsize as u32).saturating_sub(VARHDRSZ as u32)
+    }
+
+    /// Whether the stored chunks hold compressed data (`VARATT_EXTERNAL_IS_COMPRESSED`).
+    pub fn is_compressed(&self) -> bool {
+        self.extsize() < self.raw_size()
+    }
+
+    /// The compression method, when the value is compressed.
+    pub fn compression_method(&self) -> Option<CompressionMethod> {
+        self.is_compressed()
+            .then(|| CompressionMethod::from_id(self.va_extinfo >> VARLENA_EXTSIZE_BITS))
+    }
+}
+
+/// The form a varlena is stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarlenaHeader {
+    /// One-byte header, inline and uncompressed (`VARATT_IS_1B`).
+    Short,
+    /// Four-byte header, inline and uncompressed (`VARATT_IS_4B_U`).
+    Long,
+    /// Four-byte header, inline and compressed (`VARATT_IS_4B_C`).
+    Compressed {
+        /// Size of the value once decompressed, excluding its varlena header.
+        raw_size: u32,
+        method: CompressionMethod,
+    },
+    /// Pointer to a value stored out of line in a TOAST table (`VARTAG_ONDISK`).
+    External(VarattExternal),
+}
+
+/// A varlena borrowed from a tuple.
+#[derive(Debug, Clone, Copy)]
+pub struct Varlena<'a> {
+    header: VarlenaHeader,
+    /// The whole value, header included.
+    raw: &'a [u8],
+    /// The bytes after the header: the value, compressed data, or the TOAST pointer.
+    data: &'a [u8],
+}
+
+impl<'a> Varlena<'a> {
+    /// Parses the varlena at the start of `bytes`, which may run on past its end.
+    pub fn parse(bytes: &'a [u8], endianness: Endianness) -> io::Result<Varlena<'a>> {
+        let first = *bytes.first().ok_or_else(truncated)?;
+        let (is_1b, is_1b_e, is_4b_c) = match endianness {
+            Endianness::LittleEndian => (first & 0x01 == 0x01, first == 0x01, first & 0x03 == 0x02),
+            Endianness::BigEndian => (first & 0x80 == 0x80, first == 0x80, first & 0xC0 == 0x40),
+        };
+
+        if is_1b_e {
+            let tag = *bytes.get(1).ok_or_else(truncated)?;
+            let pointer = match tag {
+                VARTAG_ONDISK => &bytes
+                    .get(VARHDRSZ_EXTERNAL..VARHDRSZ_EXTERNAL + SIZE_OF_VARATT_EXTERNAL)
+                    .ok_or_else(truncated)?,
+                VARTAG_INDIRECT | VARTAG_EXPANDED_RO | VARTAG_EXPANDED_RW => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("In-memory varlena pointer (tag {}) found on disk", tag),
+                    ));
+                }
+                tag => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unknown external varlena tag {}", tag),
+                    ));
+                }
+            };
+            let external = VarattExternal {
+                va_rawsize: u32_at(pointer, 0, endianness) as i32,
+                va_extinfo: u32_at(pointer, 4, endianness),
+                va_valueid: u32_at(pointer, 8, endianness),
+                va_toastrelid: u32_at(pointer, 12, endianness),
+            };
+            let size = VARHDRSZ_EXTERNAL + SIZE_OF_VARATT_EXTERNAL;
+            return Ok(Varlena {
+                header: VarlenaHeader::External(external),
+                raw: &bytes[..size],
+                data: pointer,
+            });
+        }
+
+        if is_1b {
+            let size = match endianness {
+                Endianness::LittleEndian => (first >> 1) as usize,
+                Endianness::BigEndian => (first & 0x7F) as usize,
+            };
+            return Varlena::split(bytes, VarlenaHeader::Short, size, VARHDRSZ_SHORT);
+        }
+
+        let word = u32_at(bytes.get(..VARHDRSZ).ok_or_else(truncated)?, 0, endianness);
+        let size = match endianness {
+            Endianness::LittleEndian => (word >> 2) & VARLENA_EXTSIZE_MASK,
+            Endianness::BigEndian => word & VARLENA_EXTSIZE_MASK,
+        } as usize;
+        if !is_4b_c {
+            return Varlena::split(bytes, VarlenaHeader::Long, size, VARHDRSZ);
+        }
+
+        let tcinfo = u32_at(
+            bytes.get(..VARHDRSZ_COMPRESSED).ok_or_else(truncated)?,
+            VARHDRSZ,
+            endianness,
+        );
+        let header = VarlenaHeader::Compressed {
+            raw_size: tcinfo & VARLENA_EXTSIZE_MASK,
+            method: CompressionMethod::from_id(tcinfo >> VARLENA_EXTSIZE_BITS),
+        };
+        Varlena::split(bytes, header, size, VARHDRSZ_COMPRESSED)
+    }
+
+    fn split(
+        bytes: &'a [u8],
+        header: VarlenaHeader,
+        size: usize,
+        header_size: usize,
+    ) -> io::Result<Varlena<'a>> {
+        if size < header_size || size > bytes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Varlena size {} does not fit its {}-byte header and the {} bytes available",
+                    size,
+                    header_size,
+                    bytes.len()
+                ),
+            ));
+        }
+        Ok(Varlena {
+            header,
+            raw: &bytes[..size],
+            data: &bytes[header_size..size],
+        })
+    }
+
+    pub fn header(&self) -> VarlenaHeader {
+        self.header
+    }
+
+    /// Total size including the header (`VARSIZE_ANY`).
+    pub fn size(&self) -> usize {
+        self.raw.len()
+    }
+
+    /// The whole value, header included.
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// The bytes following the header.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The value itself, if it is stored inline and uncompressed.
+    pub fn inline_data(&self) -> Option<&'a [u8]> {
+        match self.header {
+            VarlenaHeader::Short | VarlenaHeader::Long => Some(self.data),
+            _ => None,
+        }
+    }
+
+    /// How the value is compressed, inline or in its TOAST table; `None` if it is not.
+    pub fn compression_method(&self) -> Option<CompressionMethod> {
+        match self.header {
+            VarlenaHeader::Compressed { method, .. } => Some(method),
+            VarlenaHeader::External(external) => external.compression_method(),
+            VarlenaHeader::Short | VarlenaHeader::Long => None,
+        }
+    }
+
+    /// The value itself, decompressing it if it is stored compressed inline.
+    ///
+    /// External values live in a TOAST table and cannot be read from the varlena alone.
+    pub fn value(&self) -> io::Result<Cow<'a, [u8]>> {
+        match self.header {
+            VarlenaHeader::Short | VarlenaHeader::Long => Ok(Cow::Borrowed(self.data)),
+            VarlenaHeader::Compressed { raw_size, method } => {
+                decompress(self.data, raw_size as usize, method).map(Cow::Owned)
+            }
+            VarlenaHeader::External(external) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "Value {} is stored out of line in TOAST relation {}",
+                    external.va_valueid(),
+                    external.va_toastrelid()
+                ),
+            )),
+        }
+    }
+}
+
+/// Decompresses `data` into `raw_size` bytes using `method`.
+pub fn decompress(data: &[u8], raw_size: usize, method: CompressionMethod) -> io::Result<Vec<u8>> {
+    match method {
+        CompressionMethod::Pglz => pglz_decompress(data, raw_size),
+        #[cfg(feature = "lz4")]
+        CompressionMethod::Lz4 => lz4_decompress(data, raw_size),
+        method => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Unsupported compression method {}", method),
+        )),
+    }
+}
+
+/// Decompresses an LZ4 block, as written by `lz4_compress_datum`.
+#[cfg(feature = "lz4")]
+fn lz4_decompress(data: &[u8], raw_size: usize) -> io::Result<Vec<u8>> {
+    if raw_size > MAX_ALLOC_SIZE || raw_size > data.len().saturating_mul(LZ4_MAX_EXPANSION) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Compressed lz4 data is corrupt: {} bytes cannot expand to {} bytes",
+                data.len(),
+                raw_size
+            ),
+        ));
+    }
+    let mut dest = vec![0u8; raw_size];
+    let written = lz4_flex::block::decompress_into(data, &mut dest).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Compressed lz4 data is corrupt: {}", err),
+        )
+    })?;
+    if written != raw_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Compressed lz4 data is corrupt: produced {} of {} bytes",
+                written, raw_size
+            ),
+        ));
+    }
+    Ok(dest)
+}
+
+fn u32_at(bytes: &[u8], offset: usize, endianness: Endianness) -> u32 {
+    let word = [
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ];
+    match endianness {
+        Endianness::LittleEndian => u32::from_le_bytes(word),
+        Endianness::BigEndian => u32::from_be_bytes(word),
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Truncated varlena header")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_and_long_headers() {
+        let little = [0x0b, b'a', b'b', b'c', b'd', 0xff];
+        let big = [0x85, b'a', b'b', b'c', b'd', 0xff];
+        for (bytes, endianness) in [
+            (&little, Endianness::LittleEndian),
+            (&big, Endianness::BigEndian),
+        ] {
+            let varlena = Varlena::parse(bytes, endianness).unwrap();
+            assert_eq!(varlena.header(), VarlenaHeader::Short);
+            assert_eq!(varlena.inline_data(), Some(&b"abcd"[..]));
+        }
+
+        let little = [0x20, 0x00, 0x00, 0x00, 1, 2, 3, 4];
+        let big = [0x00, 0x00, 0x00, 0x08, 1, 2, 3, 4];
+        for (bytes, endianness) in [
+            (&little, Endianness::LittleEndian),
+            (&big, Endianness::BigEndian),
+        ] {
+            let varlena = Varlena::parse(bytes, endianness).unwrap();
+            assert_eq!(varlena.header(), VarlenaHeader::Long);
+            assert_eq!(varlena.data(), &[1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn compressed_header() {
+        // 12 bytes in total, 1000 bytes raw, LZ4.
+        let mut little = vec![0x32, 0x00, 0x00, 0x00];
+        little.extend_from_slice(&(1000u32 | 1 << 30).to_le_bytes());
+        little.extend_from_slice(&[9, 9, 9, 9]);
+        let mut big = vec![0x40, 0x00, 0x00, 0x0c];
+        big.extend_from_slice(&(1000u32 | 1 << 30).to_be_bytes());
+        big.extend_from_slice(&[9, 9, 9, 9]);
+
+        for (bytes, endianness) in [
+            (&little, Endianness::LittleEndian),
+            (&big, Endianness::BigEndian),
+        ] {
+            let varlena = Varlena::parse(bytes, endianness).unwrap();
+            assert_eq!(
+                varlena.header(),
+                VarlenaHeader::Compressed {
+                    raw_size: 1000,
+                    method: CompressionMethod::Lz4
+                }
+            );
+            assert_eq!(varlena.data(), &[9, 9, 9, 9]);
+        }
+    }
+
+    #[test]
+    fn external_pointer() {
+        // The one-byte header reads 0x01 little-endian and 0x80 big-endian; the
+        // fields after the tag are in the machine's byte order.
+        let pointer = |first: u8, to_bytes: fn(u32) -> [u8; 4], extinfo: u32| {
+            let mut bytes = vec![first, VARTAG_ONDISK];
+            for field in [5004, extinfo, 16400, 16401] {
+                bytes.extend_from_slice(&to_bytes(field));
+            }
+            bytes
+        };
+        for (first, to_bytes, endianness) in [
+            (
+                0x01,
+                u32::to_le_bytes as fn(u32) -> [u8; 4],
+                Endianness::LittleEndian,
+            ),
+            (0x80, u32::to_be_bytes, Endianness::BigEndian),
+        ] {
+            let bytes = pointer(first, to_bytes, 5000);
+            let varlena = Varlena::parse(&bytes, endianness).unwrap();
+            let VarlenaHeader::External(external) = varlena.header() else {
+                panic!("expected an external pointer");
+            };
+            assert_eq!(varlena.size(), 18);
+            assert_eq!(external.va_rawsize(), 5004);
+            assert_eq!(external.va_valueid(), 16400);
+            assert_eq!(external.va_toastrelid(), 16401);
+            assert!(!external.is_compressed());
+            assert_eq!(external.compression_method(), None);
+
+            // Stored LZ4-compressed in 3000 bytes.
+            let bytes = pointer(first, to_bytes, 3000 | 1 << 30);
+            let varlena = Varlena::parse(&bytes, endianness).unwrap();
+            let VarlenaHeader::External(external) = varlena.header() else {
+                panic!("expected an external pointer");
+            };
+            assert_eq!(external.extsize(), 3000);
+            assert!(external.is_compressed());
+            assert_eq!(external.compression_method(), Some(CompressionMethod::Lz4));
+        }
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn lz4_value() {
+        let text = b"lz4 lz4 lz4 lz4 lz4 lz4 lz4 lz4 lz4 lz4 lz4 lz4".repeat(4);
+        let compressed = lz4_flex::block::compress(&text);
+        let size = (VARHDRSZ_COMPRESSED + compressed.len()) as u32;
+        let mut bytes = (size << 2 | 0x02).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&(text.len() as u32 | 1 << 30).to_le_bytes());
+        bytes.extend_from_slice(&compressed);
+
+        let varlena = Varlena::parse(&bytes, Endianness::LittleEndian).unwrap();
+        assert_eq!(varlena.compression_method(), Some(CompressionMethod::Lz4));
+        assert_eq!(varlena.value().unwrap(), &text[..]);
+        assert!(decompress(
+            &compressed[..compressed.len() - 1],
+            text.len(),
+            CompressionMethod::Lz4
+        )
+        .is_err());
+        // A corrupt header must not get a 1 GiB buffer allocated for it.
+        assert!(decompress(&compressed, 0x3FFF_FFFF, CompressionMethod::Lz4).is_err());
+        assert!(decompress(
+            &compressed,
+            compressed.len() * 255 + 1,
+            CompressionMethod::Lz4
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! Borrowed, lazily parsed views over page bytes.
+//!
+//! These mirror `PageLayout`, `PageItem` and `HeapTuple` but only decode what is
+//! asked for and never copy tuple data, so scanning a mapped relation does not
+//! allocate per tuple.
+
+use crate::checksum::{self, ChecksumStatus};
+use crate::datum::{decode_tuple, Datum, TupleDescriptor};
+use crate::{
+    att_isnull, item_data, read_page_header, BlockNumber, Endianness, HeapTupleHeaderData,
+    ItemIdData, LPFlags, PageHeaderData, TupleSections, SIZE_OF_HEAP_TUPLE_HEADER,
+    SIZE_OF_ITEM_ID_DATA, SIZE_OF_PAGE_HEADER_DATA,
+};
+use std::io;
+
+/// A page borrowed from a buffer or mapping.
+#[derive(Debug, Clone, Copy)]
+pub struct PageRef<'a> {
+    data: &'a [u8],
+    block_number: BlockNumber,
+    endianness: Endianness,
+    item_count: usize,
+}
+
+impl<'a> PageRef<'a> {
+    /// Wraps the bytes of one block, checking only the header bounds.
+    pub fn new(
+        data: &'a [u8],
+        block_number: BlockNumber,
+        endianness: Endianness,
+    ) -> io::Result<PageRef<'a>> {
+        let header = read_page_header(&mut &data[..], endianness)?;
+        header.check_page_size(data.len(), block_number)?;
+        let item_count = header.item_count(data.len())?;
+
+        Ok(PageRef {
+            data,
+            block_number,
+            endianness,
+            item_count,
+        })
+    }
+
+    pub fn block_number(&self) -> BlockNumber {
+        self.block_number
+    }
+
+    /// The raw bytes of the page.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Decodes the page header.
+    pub fn header(&self) -> PageHeaderData {
+        read_page_header(&mut &self.data[..], self.endianness)
+            .expect("header was decoded when the page was wrapped")
+    }
+
+    /// Verifies the page checksum.
+    pub fn checksum(&self) -> ChecksumStatus {
+        checksum::verify_page_checksum(self.data, self.block_number, self.endianness)
+    }
+
+    /// Number of line pointers on the page.
+    pub fn item_count(&self) -> usize {
+        self.item_count
+    }
+
+    /// Decodes the line pointer at `index` (zero-based; offset numbers start at one).
+    pub fn item_id(&self, index: usize) -> Option<ItemIdData> {
+        if index >= self.item_count {
+            return None;
+        }
+        let start = SIZE_OF_PAGE_HEADER_DATA + index * SIZE_OF_ITEM_ID_DATA;
+        let mut word = self.data.get(start..start + SIZE_OF_ITEM_ID_DATA)?;
+        ItemIdData::from_reader(&mut word, self.endianness).ok()
+    }
+
+    /// Resolves the line pointer at `index` against the page.
+    pub fn item(&self, index: usize) -> Option<io::Result<ItemRef<'a>>> {
+        let item_id = self.item_id(index)?;
+        let item = match *item_id.lp_flags() {
+            LPFlags::LP_NORMAL => item_data(self.data, &item_id)
+                .and_then(|data| HeapTupleRef::new(data, self.endianness).map(ItemRef::Normal)),
+            LPFlags::LP_REDIRECT => Ok(ItemRef::Redirect(item_id.lp_off())),
+            LPFlags::LP_DEAD => Ok(ItemRef::Dead),
+            _ => Ok(ItemRef::Unused),
+        };
+        Some(item)
+    }
+
+    /// Iterates over the items of the page in line pointer order.
+    pub fn items(&self) -> impl Iterator<Item = io::Result<ItemRef<'a>>> + '_ {
+        (0..self.item_count).filter_map(move |index| self.item(index))
+    }
+
+    /// The special space between `pd_special` and the end of the page, if any.
+    pub fn special_space(&self) -> Option<&'a [u8]> {
+        let special = **self.header().pd_special() as usize;
+        if special > 0 && special < self.data.len() {
+            Some(&self.data[special..])
+        } else {
+            None
+        }
+    }
+}
+
+/// A line pointer resolved against a borrowed page.
+#[derive(Debug, Clone, Copy)]
+pub enum ItemRef<'a> {
+    /// The line pointer is free and has no storage.
+    Unused,
+    /// The line pointer points at a tuple stored at `lp_off`.
+    Normal(HeapTupleRef<'a>),
+    /// HOT redirect; holds the offset number of the line pointer it forwards to.
+    Redirect(u16),
+    /// The tuple is dead; any remaining storage is not decoded.
+    Dead,
+}
+
+/// A heap tuple borrowed from its page.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapTupleRef<'a> {
+    raw: &'a [u8],
+    endianness: Endianness,
+    natts: u16,
+    null_bitmap: Option<&'a [u8]>,
+    oid: Option<u32>,
+    user_data: &'a [u8],
+}
+
+impl<'a> HeapTupleRef<'a> {
+    /// Wraps the `lp_len` bytes of a tuple, locating its null bitmap and user data.
+    pub fn new(data: &'a [u8], endianness: Endianness) -> io::Result<HeapTupleRef<'a>> {
+        if data.len() < SIZE_OF_HEAP_TUPLE_HEADER {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Tuple length {} is shorter than its header", data.len()),
+            ));
+        }
+        let header = HeapTupleHeaderData::read_from(&mut &data[..], endianness)?;
+        let sections =
+            TupleSections::split(&header, &data[SIZE_OF_HEAP_TUPLE_HEADER..], endianness)?;
+
+        Ok(HeapTupleRef {
+            raw: data,
+            endianness,
+            natts: header.natts(),
+            null_bitmap: sections.null_bitmap,
+            oid: sections.oid,
+            user_data: sections.user_data,
+        })
+    }
+
+    /// Decodes the fixed tuple header.
+    pub fn header(&self) -> HeapTupleHeaderData {
+        HeapTupleHeaderData::read_from(&mut &self.raw[..], self.endianness)
+            .expect("tuple is at least as long as its header")
+    }
+
+    /// The whole tuple, header included.
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// Number of attributes stored in the tuple.
+    pub fn natts(&self) -> u16 {
+        self.natts
+    }
+
+    /// The null bitmap, present when `HEAP_HASNULL` is set.
+    pub fn null_bitmap(&self) -> Option<&'a [u8]> {
+        self.null_bitmap
+    }
+
+    /// The tuple OID of a table created `WITH OIDS` before PostgreSQL 12.
+    pub fn oid(&self) -> Option<u32> {
+        self.oid
+    }
+
+    /// User data, starting at `t_hoff`, as `HeapTuple::data` holds it.
+    pub fn data(&self) -> &'a [u8] {
+        self.user_data
+    }
+
+    /// Whether the attribute at zero-based `index` is null.
+    pub fn is_null(&self, index: usize) -> bool {
+        att_isnull(self.null_bitmap, self.natts, index)
+    }
+
+    /// Decodes the tuple's user data into one value per attribute of `descriptor`.
+    pub fn decode(&self, descriptor: &TupleDescriptor) -> io::Result<Vec<Datum>> {
+        decode_tuple(
+            descriptor,
+            self.user_data,
+            self.null_bitmap,
+            self.natts,
+            self.endianness,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PageItem, PageLayout, DEFAULT_POSTGRES_PAGE_SIZE};
+
+    #[test]
+    fn borrowed_view_matches_owned_layout() {
+        let mut page = vec![0u8; DEFAULT_POSTGRES_PAGE_SIZE];
+        page[12..14].copy_from_slice(&32u16.to_le_bytes());
+        page[14..16].copy_from_slice(&8160u16.to_le_bytes());
+        page[16..18].copy_from_slice(&8192u16.to_le_bytes());
+        page[18..20].copy_from_slice(&0x2004u16.to_le_bytes());
+        // One normal tuple of 32 bytes at 8160 and a redirect to it.
+        page[24..28].copy_from_slice(&[0xe0, 0x9f, 0x40, 0x00]);
+        page[28..32].copy_from_slice(&[0x01, 0x00, 0x01, 0x00]);
+        page[8160..8164].copy_from_slice(&742u32.to_le_bytes());
+        page[8182] = 24;
+        page[8184..8192].copy_from_slice(b"payload!");
+
+        let owned =
+            PageLayout::from_bytes(&page, BlockNumber(0), Endianness::LittleEndian).unwrap();
+        let view = PageRef::new(&page, BlockNumber(0), Endianness::LittleEndian).unwrap();
+        assert_eq!(view.item_count(), owned.items().len());
+
+        let items = view.items().collect::<io::Result<Vec<_>>>().unwrap();
+        match (&items[0], &owned.items()[0]) {
+            (ItemRef::Normal(borrowed), PageItem::Normal(tuple)) => {
+                assert_eq!(borrowed.data(), tuple.data());
+                assert_eq!(**borrowed.header().t_xmin(), 742);
+            }
+            other => panic!("unexpected items {:?}", other),
+        }
+        assert!(matches!(items[1], ItemRef::Redirect(1)));
+    }
+
+    #[test]
+    fn rejects_bounds_past_the_page() {
+        let mut page = vec![0u8; DEFAULT_POSTGRES_PAGE_SIZE];
+        page[12..14].copy_from_slice(&10000u16.to_le_bytes());
+        page[14..16].copy_from_slice(&10000u16.to_le_bytes());
+        page[16..18].copy_from_slice(&10000u16.to_le_bytes());
+        page[18..20].copy_from_slice(&0x2004u16.to_le_bytes());
+
+        let err = PageRef::new(&page, BlockNumber(0), Endianness::LittleEndian).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(PageLayout::from_bytes(&page, BlockNumber(0), Endianness::LittleEndian).is_err());
+    }
+}
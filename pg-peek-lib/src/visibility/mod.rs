@@ -0,0 +1,472 @@
+//! MVCC visibility of heap tuples against a snapshot (`heapam_visibility.c`).
+//!
+//! A tuple's fate depends on the transactions in `t_xmin` and `t_xmax`. Hint bits
+//! in `t_infomask` record outcomes already looked up; anything else is asked of a
+//! [`TransactionOracle`], such as a reader of `pg_xact`.
+
+use crate::clog::ClogReader;
+use crate::control::ControlFileData;
+use crate::multixact::MultiXactReader;
+use crate::subtrans::SubtransReader;
+use crate::{HeapTupleHeaderData, Infomask, TransactionId};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use strum_macros::Display;
+
+/// State of a transaction as recorded in `pg_xact` (`XidStatus`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum TransactionStatus {
+    InProgress,
+    Committed,
+    Aborted,
+    /// A subtransaction that committed, pending the outcome of its parent.
+    SubCommitted,
+}
+
+/// Source of transaction outcomes not already recorded in hint bits.
+pub trait TransactionOracle {
+    /// The status of `xid`.
+    fn status(&self, xid: TransactionId) -> io::Result<TransactionStatus>;
+
+    /// The updating member of the MultiXact `multi`, if it has one.
+    ///
+    /// Oracles that cannot read `pg_multixact` return `None`, so tuples whose
+    /// `t_xmax` is a MultiXact are treated as only locked.
+    fn multixact_updater(&self, _multi: u32) -> io::Result<Option<TransactionId>> {
+        Ok(None)
+    }
+
+    /// The parent of the subtransaction `xid`, if it is one.
+    ///
+    /// Oracles that cannot read `pg_subtrans` return `None`, so subcommitted xids
+    /// are treated as still running.
+    fn parent(&self, _xid: TransactionId) -> io::Result<Option<TransactionId>> {
+        Ok(None)
+    }
+}
+
+impl<F> TransactionOracle for F
+where
+    F: Fn(TransactionId) -> io::Result<TransactionStatus>,
+{
+    fn status(&self, xid: TransactionId) -> io::Result<TransactionStatus> {
+        self(xid)
+    }
+}
+
+/// Looks transaction outcomes up in a data directory's `pg_xact`, `pg_multixact`
+/// and `pg_subtrans`.
+pub struct ClusterOracle {
+    clog: ClogReader,
+    multixact: MultiXactReader,
+    subtrans: SubtransReader,
+}
+
+impl ClusterOracle {
+    pub fn new(
+        clog: ClogReader,
+        multixact: MultiXactReader,
+        subtrans: SubtransReader,
+    ) -> ClusterOracle {
+        ClusterOracle {
+            clog,
+            multixact,
+            subtrans,
+        }
+    }
+
+    /// Opens the SLRUs of `data_directory`, configured from its `pg_control`.
+    pub fn open(data_directory: &Path, control: &ControlFileData) -> ClusterOracle {
+        let block_size = control.blcksz() as usize;
+        let endianness = control.endianness();
+        let checkpoint = control.check_point_copy();
+        let mut multixact = MultiXactReader::open(data_directory, block_size, endianness);
+        multixact.set_next(checkpoint.next_multi(), checkpoint.next_multi_offset());
+        ClusterOracle::new(
+            ClogReader::open(data_directory, block_size),
+            multixact,
+            SubtransReader::open(data_directory, block_size, endianness),
+        )
+    }
+
+    pub fn clog(&self) -> &ClogReader {
+        &self.clog
+    }
+
+    pub fn multixact(&self) -> &MultiXactReader {
+        &self.multixact
+    }
+
+    pub fn subtrans(&self) -> &SubtransReader {
+        &self.subtrans
+    }
+}
+
+impl TransactionOracle for ClusterOracle {
+    fn status(&self, xid: TransactionId) -> io::Result<TransactionStatus> {
+        self.clog.status(xid)
+    }
+
+    fn multixact_updater(&self, multi: u32) -> io::Result<Option<TransactionId>> {
+        self.multixact.updater(multi)
+    }
+
+    fn parent(&self, xid: TransactionId) -> io::Result<Option<TransactionId>> {
+        // Segments for xids older than the oldest running transaction are removed.
+        match self.subtrans.parent(xid) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            result => result,
+        }
+    }
+}
+
+/// How far past a possibly stale `nextXid` [`Snapshot::latest`] reaches.
+pub const LATEST_SNAPSHOT_SLACK: u32 = 1 << 24;
+
+/// An MVCC snapshot, as shown by `pg_current_snapshot()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    xmin: TransactionId,
+    xmax: TransactionId,
+    xip: Vec<TransactionId>,
+}
+
+impl Snapshot {
+    /// Every xid before `xmin` has finished, every xid from `xmax` on had not
+    /// started, and `xip` lists those in between that were still running.
+    pub fn new(xmin: TransactionId, xmax: TransactionId, xip: Vec<TransactionId>) -> Snapshot {
+        Snapshot { xmin, xmax, xip }
+    }
+
+    /// A snapshot seeing every transaction the oracle reports as committed, as if
+    /// taken once all running ones had finished.
+    ///
+    /// `next_xid` need only be roughly current, like the checkpoint's `nextXid` in
+    /// `pg_control`: xids assigned up to `LATEST_SNAPSHOT_SLACK` past it still count
+    /// as started before the snapshot.
+    pub fn latest(next_xid: TransactionId) -> Snapshot {
+        let mut xmax = next_xid.0.wrapping_add(LATEST_SNAPSHOT_SLACK);
+        if xmax < TransactionId::FIRST_NORMAL.0 {
+            xmax += TransactionId::FIRST_NORMAL.0;
+        }
+        Snapshot::new(TransactionId(xmax), TransactionId(xmax), Vec::new())
+    }
+
+    pub fn xmin(&self) -> TransactionId {
+        self.xmin
+    }
+
+    pub fn xmax(&self) -> TransactionId {
+        self.xmax
+    }
+
+    pub fn xip(&self) -> &[TransactionId] {
+        &self.xip
+    }
+
+    /// Whether `xid` was still running, or not yet started, when the snapshot was
+    /// taken (`XidInMVCCSnapshot`).
+    pub fn is_running(&self, xid: TransactionId) -> bool {
+        if xid.precedes(self.xmin) {
+            return false;
+        }
+        if !xid.precedes(self.xmax) {
+            return true;
+        }
+        self.xip.contains(&xid)
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = String;
+
+    /// Parses the `xmin:xmax:xip1,xip2,...` text form of `pg_snapshot`.
+    fn from_str(s: &str) -> Result<Snapshot, String> {
+        let parse = |xid: &str| {
+            xid.trim()
+                .parse()
+                .map(TransactionId)
+                .map_err(|_| format!("invalid xid '{}' in snapshot '{}'", xid, s))
+        };
+        let mut parts = s.splitn(3, ':');
+        let (Some(xmin), Some(xmax)) = (parts.next(), parts.next()) else {
+            return Err(format!("snapshot '{}' is not of the form xmin:xmax:xip", s));
+        };
+        let xip = match parts.next() {
+            Some(xip) if !xip.trim().is_empty() => {
+                xip.split(',').map(parse).collect::<Result<_, _>>()?
+            }
+            _ => Vec::new(),
+        };
+        let snapshot = Snapshot::new(parse(xmin)?, parse(xmax)?, xip);
+        if snapshot.xmax.precedes(snapshot.xmin) {
+            return Err(format!("snapshot '{}' has xmax before xmin", s));
+        }
+        Ok(snapshot)
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:", self.xmin, self.xmax)?;
+        for (index, xid) in self.xip.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", xid)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a snapshot makes of a tuple, in the spirit of `HTSV_Result`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum TupleVisibility {
+    /// Inserted by a transaction the snapshot sees as committed, and not deleted.
+    Visible,
+    /// Visible, with `t_xmax` only locking the tuple rather than deleting it.
+    LockedOnly,
+    /// Visible, but deleted by a transaction still running as of the snapshot.
+    DeleteInProgress,
+    /// Inserted by a transaction still running as of the snapshot.
+    InsertInProgress,
+    /// Deleted by a transaction the snapshot sees as committed, but one that
+    /// finished after the snapshot's `xmin`, so older snapshots may still see it.
+    RecentlyDead,
+    /// Inserted by an aborted transaction, or deleted before the snapshot's `xmin`.
+    Dead,
+}
+
+impl TupleVisibility {
+    /// Whether a query using the snapshot would return the tuple.
+    pub fn is_visible(&self) -> bool {
+        matches!(
+            self,
+            TupleVisibility::Visible
+                | TupleVisibility::LockedOnly
+                | TupleVisibility::DeleteInProgress
+        )
+    }
+}
+
+/// Whether `t_xmax` only locks the tuple (`HEAP_XMAX_IS_LOCKED_ONLY`).
+pub fn xmax_is_locked_only(infomask: &Infomask) -> bool {
+    infomask.contains(Infomask::HEAP_XMAX_LOCK_ONLY)
+        || infomask.intersection(Infomask::HEAP_XMAX_IS_MULTI | Infomask::HEAP_LOCK_MASK)
+            == Infomask::HEAP_XMAX_EXCL_LOCK
+}
+
+/// The outcome of `xid`, from its hint bits if set, otherwise from `oracle`.
+fn transaction_status(
+    xid: TransactionId,
+    hinted_committed: bool,
+    hinted_invalid: bool,
+    oracle: &dyn TransactionOracle,
+) -> io::Result<TransactionStatus> {
+    if hinted_committed {
+        return Ok(TransactionStatus::Committed);
+    }
+    if hinted_invalid || xid == TransactionId::INVALID {
+        return Ok(TransactionStatus::Aborted);
+    }
+    if !xid.is_normal() {
+        return Ok(TransactionStatus::Committed);
+    }
+    match oracle.status(xid)? {
+        // A subcommitted xid shares its parent's fate; without one it is as good as running.
+        TransactionStatus::SubCommitted => match oracle.parent(xid)? {
+            Some(parent) if parent.precedes(xid) => {
+                transaction_status(parent, false, false, oracle)
+            }
+            _ => Ok(TransactionStatus::InProgress),
+        },
+        status => Ok(status),
+    }
+}
+
+/// Whether a TOAST chunk may belong to a value, in the spirit of
+/// `HeapTupleSatisfiesToast`: any chunk but those of aborted insertions.
+///
+/// Values are only reached through tuples whose own visibility is checked, so
+/// chunks need no snapshot; chunks of deleted values stay readable.
+pub fn toast_chunk_visible(
+    header: &HeapTupleHeaderData,
+    oracle: &dyn TransactionOracle,
+) -> io::Result<bool> {
+    let infomask = header.t_infomask();
+    let inserter = transaction_status(
+        *header.t_xmin(),
+        infomask.contains(Infomask::HEAP_XMIN_COMMITTED),
+        infomask.contains(Infomask::HEAP_XMIN_INVALID),
+        oracle,
+    )?;
+    Ok(inserter != TransactionStatus::Aborted)
+}
+
+/// Whether `xid` was running as of `snapshot`, counting a subtransaction as running
+/// with its top-level parent, since snapshots only list top-level xids.
+fn running_in_snapshot(
+    snapshot: &Snapshot,
+    xid: TransactionId,
+    oracle: &dyn TransactionOracle,
+) -> io::Result<bool> {
+    if snapshot.is_running(xid) {
+        return Ok(true);
+    }
+    if xid.precedes(snapshot.xmin) {
+        return Ok(false);
+    }
+    let mut current = xid;
+    while let Some(parent) = oracle.parent(current)? {
+        if !parent.precedes(current) {
+            break;
+        }
+        if snapshot.is_running(parent) {
+            return Ok(true);
+        }
+        current = parent;
+    }
+    Ok(false)
+}
+
+/// Classifies a tuple by its header against `snapshot`.
+pub fn tuple_visibility(
+    header: &HeapTupleHeaderData,
+    snapshot: &Snapshot,
+    oracle: &dyn TransactionOracle,
+) -> io::Result<TupleVisibility> {
+    let infomask = header.t_infomask();
+    let xmin = *header.t_xmin();
+    let frozen = infomask.contains(Infomask::HEAP_XMIN_FROZEN);
+
+    let inserter = transaction_status(
+        xmin,
+        infomask.contains(Infomask::HEAP_XMIN_COMMITTED),
+        infomask.contains(Infomask::HEAP_XMIN_INVALID),
+        oracle,
+    )?;
+    match inserter {
+        TransactionStatus::Aborted => return Ok(TupleVisibility::Dead),
+        TransactionStatus::Committed if frozen || !running_in_snapshot(snapshot, xmin, oracle)? => {
+        }
+        _ => return Ok(TupleVisibility::InsertInProgress),
+    }
+
+    let xmax = *header.t_xmax();
+    if infomask.contains(Infomask::HEAP_XMAX_INVALID) || xmax == TransactionId::INVALID {
+        return Ok(TupleVisibility::Visible);
+    }
+    if xmax_is_locked_only(infomask) {
+        return Ok(TupleVisibility::LockedOnly);
+    }
+
+    let (deleter, hinted_committed) = if infomask.contains(Infomask::HEAP_XMAX_IS_MULTI) {
+        match oracle.multixact_updater(xmax.0)? {
+            Some(updater) => (updater, false),
+            None => return Ok(TupleVisibility::LockedOnly),
+        }
+    } else {
+        (xmax, infomask.contains(Infomask::HEAP_XMAX_COMMITTED))
+    };
+
+    Ok(
+        match transaction_status(deleter, hinted_committed, false, oracle)? {
+            TransactionStatus::Aborted => TupleVisibility::Visible,
+            TransactionStatus::Committed if !running_in_snapshot(snapshot, deleter, oracle)? => {
+                if deleter.precedes(snapshot.xmin) {
+                    TupleVisibility::Dead
+                } else {
+                    TupleVisibility::RecentlyDead
+                }
+            }
+            _ => TupleVisibility::DeleteInProgress,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Endianness;
+
+    fn header(xmin: u32, xmax: u32, infomask: Infomask) -> HeapTupleHeaderData {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&xmin.to_le_bytes());
+        bytes.extend_from_slice(&xmax.to_le_bytes());
+        bytes.extend_from_slice(&[0; 10]);
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&infomask.bits().to_le_bytes());
+        bytes.push(24);
+        HeapTupleHeaderData::read_from(&mut &bytes[..], Endianness::LittleEndian).unwrap()
+    }
+
+    #[test]
+    fn classifies_against_snapshot() {
+        // 700 aborted, 720 still running, everything else committed.
+        let oracle = |xid: TransactionId| {
+            Ok(match *xid {
+                700 => TransactionStatus::Aborted,
+                720 => TransactionStatus::InProgress,
+                _ => TransactionStatus::Committed,
+            })
+        };
+        let snapshot: Snapshot = "710:730:720,725".parse().unwrap();
+        assert_eq!(snapshot.to_string(), "710:730:720,725");
+
+        let cases = [
+            (
+                header(600, 0, Infomask::HEAP_XMAX_INVALID),
+                TupleVisibility::Visible,
+            ),
+            (header(700, 0, Infomask::empty()), TupleVisibility::Dead),
+            (
+                header(720, 0, Infomask::empty()),
+                TupleVisibility::InsertInProgress,
+            ),
+            // Committed, but after the snapshot was taken.
+            (
+                header(725, 0, Infomask::empty()),
+                TupleVisibility::InsertInProgress,
+            ),
+            (header(600, 650, Infomask::empty()), TupleVisibility::Dead),
+            (
+                header(600, 715, Infomask::empty()),
+                TupleVisibility::RecentlyDead,
+            ),
+            (
+                header(600, 720, Infomask::empty()),
+                TupleVisibility::DeleteInProgress,
+            ),
+            (
+                header(600, 700, Infomask::empty()),
+                TupleVisibility::Visible,
+            ),
+            (
+                header(600, 720, Infomask::HEAP_XMAX_LOCK_ONLY),
+                TupleVisibility::LockedOnly,
+            ),
+            // Frozen tuples are visible whatever their raw xmin says.
+            (
+                header(
+                    725,
+                    0,
+                    Infomask::HEAP_XMIN_FROZEN | Infomask::HEAP_XMAX_INVALID,
+                ),
+                TupleVisibility::Visible,
+            ),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(
+                tuple_visibility(&header, &snapshot, &oracle).unwrap(),
+                expected,
+                "xmin {} xmax {}",
+                *header.t_xmin(),
+                *header.t_xmax()
+            );
+        }
+    }
+}
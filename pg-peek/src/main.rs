@@ -1,6 +1,30 @@
-use clap::{Parser, Subcommand};
-use pg_peek_lib::{get_system_endianness, read_all_pages};
+use clap::{ArgGroup, Parser, Subcommand};
+use pg_peek_lib::catalog::{
+    relation_locator, relation_path, tablespace_version_directory, CatalogReader, DatabaseRow,
+    RelFileLocator, Relation,
+};
+use pg_peek_lib::checksum::{verify_cluster_page_checksum, verify_page_checksum, ChecksumStatus};
+use pg_peek_lib::clog::ClogReader;
+use pg_peek_lib::control::{find_data_directory, ControlFileData};
+use pg_peek_lib::datum::{Attribute, Datum, TupleDescriptor};
+use pg_peek_lib::fsm::{compare_free_space, FreeSpaceStatus, FsmReader};
+use pg_peek_lib::inventory::{inventory, FileOwner, RelationFileName, StoredRelation};
+use pg_peek_lib::multixact::{xmax_members, MultiXactReader};
+use pg_peek_lib::relation::RelationReader;
+use pg_peek_lib::relmap::RelMapFile;
+use pg_peek_lib::subtrans::SubtransReader;
+use pg_peek_lib::toast::ToastReader;
+use pg_peek_lib::types::{pg_type_by_name, pg_type_by_oid};
+use pg_peek_lib::visibility::{
+    tuple_visibility, ClusterOracle, Snapshot, TransactionOracle, TransactionStatus,
+};
+use pg_peek_lib::{
+    get_system_endianness, relseg_size, BlockNumber, Endianness, PageItem, PageLayout,
+    TransactionId,
+};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
 struct Cli {
@@ -11,23 +35,1208 @@ struct Cli {
 /// Subcommands available
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// Subcommand for handling tables
+    /// Subcommand for handling tables; later segments (`.1`, `.2`, ...) are read too
+    #[command(group(ArgGroup::new("columns").args(["types", "table"])))]
     Table {
-        #[arg(short, long)]
+        #[arg(short, long, required_unless_present = "table")]
+        filename: Option<String>,
+        /// Data directory holding `--table`
+        #[arg(short = 'D', long, requires = "table")]
+        datadir: Option<String>,
+        /// Database holding `--table`, by name or OID
+        #[arg(long, requires = "table")]
+        db: Option<String>,
+        /// Table to read instead of `--filename`, as `schema.name` or just `name` in
+        /// `public`; found through the catalogs, which also give its column types
+        #[arg(long, conflicts_with_all = ["filename", "types"], requires_all = ["datadir", "db"])]
+        table: Option<String>,
+        /// Block size in bytes, overriding pg_control and the first page header
+        #[arg(long)]
+        block_size: Option<usize>,
+        /// First block to print
+        #[arg(long, default_value_t = 0)]
+        start_block: u32,
+        /// Block to stop before; defaults to the end of the relation
+        #[arg(long)]
+        end_block: Option<u32>,
+        /// Column types as names or OIDs (e.g. `int4,text,numeric`); prints decoded rows
+        #[arg(long, value_delimiter = ',')]
+        types: Option<Vec<String>>,
+        /// With `--types` or `--table`, print each column's compression method instead of its value
+        #[arg(long, requires = "columns")]
+        compression: bool,
+        /// With `--types` or `--table`, print TOAST pointers instead of fetching the values they point to
+        #[arg(long, requires = "columns")]
+        no_toast: bool,
+        /// With `--types` or `--table`, print only rows visible to this `xmin:xmax:xip` snapshot
+        /// (as shown by `pg_current_snapshot()`)
+        #[arg(long, requires = "columns")]
+        snapshot: Option<Snapshot>,
+        /// Transactions to treat as aborted instead of reading `pg_xact`; every other
+        /// finished one then counts as committed
+        #[arg(long, requires = "snapshot", value_delimiter = ',')]
+        aborted: Vec<u32>,
+        /// With `--snapshot`, print every tuple with its visibility instead of only visible rows
+        #[arg(long, requires = "snapshot")]
+        all: bool,
+        /// With `--types` or `--table`, print the transactions locking or updating each tuple,
+        /// expanding MultiXacts through `pg_multixact`
+        #[arg(long, requires = "columns")]
+        locks: bool,
+    },
+    /// Verify page checksums of a relation file or every relation file under a directory
+    Checksums {
+        /// Relation file, or a directory such as a data directory to scan recursively
+        path: String,
+        /// Block size in bytes, overriding pg_control and the first page header
+        #[arg(long)]
+        block_size: Option<usize>,
+    },
+    /// Look up the commit status of transactions in `pg_xact`, and the parents of
+    /// subtransactions in `pg_subtrans`
+    Xact {
+        /// Transaction ids to look up
+        #[arg(required = true)]
+        xids: Vec<u32>,
+        /// Data directory holding `pg_xact`
+        #[arg(short = 'D', long)]
+        datadir: String,
+        /// Block size in bytes, overriding pg_control
+        #[arg(long)]
+        block_size: Option<usize>,
+    },
+    /// Print the contents of `global/pg_control`, like `pg_controldata`
+    Control {
+        /// Data directory holding `global/pg_control`
+        #[arg(short = 'D', long)]
+        datadir: String,
+    },
+    /// Print the relation OID to filenode mappings of a `pg_filenode.map` file
+    Relmap {
+        /// Map file, or the directory holding one (`global` or `base/<oid>`)
+        path: String,
+    },
+    /// List the relation files of a data directory with the relation each belongs
+    /// to, as path, size, fork, segment, temp backend, kind and name
+    ///
+    /// Files no `pg_class` row points to are listed as orphans. Those only a row
+    /// inserted by a transaction still in progress points to, such as files of a
+    /// relation being created, truncated or clustered, are listed as uncommitted.
+    Inventory {
+        /// Data directory to list
+        datadir: String,
+    },
+    /// Print the free space category and bytes the `_fsm` fork records for each
+    /// block of a relation
+    Fsm {
+        /// Main fork of the relation, or its `_fsm` fork
         filename: String,
+        /// Block size in bytes, overriding pg_control and the first page header
+        #[arg(long)]
+        block_size: Option<usize>,
+        /// Compare the recorded free space with `pd_upper - pd_lower` of each page
+        #[arg(long)]
+        check: bool,
     },
 }
 
+/// Resolves `--types` entries, given as type names or OIDs, through the bundled pg_type.
+fn tuple_descriptor(types: &[String]) -> anyhow::Result<TupleDescriptor> {
+    let attributes = types
+        .iter()
+        .map(|name| {
+            let pg_type = match name.parse::<u32>() {
+                Ok(oid) => pg_type_by_oid(oid),
+                Err(_) => pg_type_by_name(name),
+            };
+            pg_type
+                .map(Attribute::from_pg_type)
+                .ok_or_else(|| anyhow::anyhow!("unknown type {}", name))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(TupleDescriptor::new(attributes))
+}
+
+/// Prints the tuples of a heap relation as rows of decoded columns.
+struct RowPrinter {
+    descriptor: TupleDescriptor,
+    /// Print each column's compression method (`pglz`, `lz4` or `-`) instead of its value.
+    compression: bool,
+    /// Fetches out-of-line values; TOAST pointers are printed as such without one.
+    toast: Option<ToastReader>,
+    /// Snapshot and commit-status oracle deciding which tuples are visible.
+    visibility: Option<(Snapshot, Box<dyn TransactionOracle>)>,
+    /// Print invisible tuples too, each with its visibility.
+    all: bool,
+    /// Expands `t_xmax` into the lock holders printed ahead of the columns.
+    locks: Option<MultiXactReader>,
+    /// Indexes of dropped columns, decoded for their layout but not printed.
+    dropped: Vec<usize>,
+    endianness: Endianness,
+}
+
+impl RowPrinter {
+    /// Prints each normal tuple of `page` as `(block,offset)` followed by its columns.
+    fn print_page(&mut self, page: &PageLayout) -> anyhow::Result<()> {
+        for (index, item) in page.items().iter().enumerate() {
+            let PageItem::Normal(tuple) = item else {
+                continue;
+            };
+            let mut columns = Vec::new();
+            if let Some((snapshot, oracle)) = &self.visibility {
+                let visibility = tuple_visibility(tuple.header(), snapshot, oracle.as_ref())?;
+                if self.all {
+                    columns.push(visibility.to_string());
+                } else if !visibility.is_visible() {
+                    continue;
+                }
+            }
+            if let Some(multixact) = &self.locks {
+                let members = xmax_members(tuple.header(), multixact)?;
+                let holders: Vec<String> = members
+                    .iter()
+                    .map(|member| format!("{}:{}", member.xid(), member.status()))
+                    .collect();
+                columns.push(if holders.is_empty() {
+                    "-".to_string()
+                } else {
+                    holders.join(",")
+                });
+            }
+
+            let values: Vec<String> = if self.compression {
+                let methods = tuple.compression(&self.descriptor, self.endianness)?;
+                methods
+                    .iter()
+                    .map(|method| method.map_or_else(|| "-".to_string(), |m| m.to_string()))
+                    .collect()
+            } else {
+                let mut values = tuple.decode(&self.descriptor, self.endianness)?;
+                if let Some(toast) = &mut self.toast {
+                    values = toast.detoast_tuple(&self.descriptor, values)?;
+                }
+                values.iter().map(ToString::to_string).collect()
+            };
+            columns.extend(
+                values
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(index, _)| !self.dropped.contains(index))
+                    .map(|(_, value)| value),
+            );
+            println!(
+                "({},{})\t{}",
+                *page.block_number(),
+                index + 1,
+                columns.join("\t")
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct ChecksumTotals {
+    files: u64,
+    blocks: u64,
+    bad: u64,
+    disabled: u64,
+}
+
+/// Returns the segment number if `name` looks like a relation file (`16384`, `16384_fsm.2`, ...).
+///
+/// Files of temporary relations (`t3_16384`) are left out.
+fn relation_segment_number(name: &str) -> Option<u32> {
+    RelationFileName::parse(name)
+        .filter(|name| !name.is_temp())
+        .map(|name| name.segment())
+}
+
+fn collect_relation_files(path: &Path, files: &mut Vec<(PathBuf, u32)>) -> anyhow::Result<()> {
+    // In a data directory only these hold relation files; pg_wal names are all digits too.
+    if path.join("PG_VERSION").is_file() && path.join("global").is_dir() {
+        for dir in ["global", "base", "pg_tblspc"] {
+            if path.join(dir).is_dir() {
+                collect_relation_files(&path.join(dir), files)?;
+            }
+        }
+    } else if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            collect_relation_files(&entry.path(), files)?;
+        }
+    } else if let Some(segment) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(relation_segment_number)
+    {
+        files.push((path.to_path_buf(), segment));
+    }
+    Ok(())
+}
+
+/// A table found by name through the catalogs of a data directory.
+struct NamedTable {
+    path: PathBuf,
+    descriptor: TupleDescriptor,
+    /// Indexes of dropped columns.
+    dropped: Vec<usize>,
+    /// The table's TOAST relation and the filenode it is stored in.
+    toast: Option<(u32, u32)>,
+}
+
+/// Looks up `table` (`schema.name`, or `name` in `public`) in database `db`,
+/// given by name or OID.
+fn find_table(
+    data_directory: &Path,
+    control: &ControlFileData,
+    db: &str,
+    table: &str,
+) -> anyhow::Result<NamedTable> {
+    let databases = CatalogReader::open_shared(data_directory, control)?.databases()?;
+    let database = databases
+        .iter()
+        .find(|database| database.datname() == db)
+        .or_else(|| {
+            let oid = db.parse::<u32>().ok()?;
+            databases.iter().find(|database| database.oid() == oid)
+        })
+        .ok_or_else(|| anyhow::anyhow!("database \"{}\" does not exist", db))?;
+
+    let mut reader = CatalogReader::open(data_directory, database, control)?;
+    let catalog = reader.read()?;
+    let (schema, name) = table.split_once('.').unwrap_or(("public", table));
+    let relation = catalog
+        .relation(schema, name)
+        .ok_or_else(|| anyhow::anyhow!("relation \"{}.{}\" does not exist", schema, name))?;
+
+    let class = relation.class();
+    let relmap_dir = if class.relisshared() {
+        data_directory.join("global")
+    } else {
+        reader.database_dir().to_path_buf()
+    };
+    let relmap = RelMapFile::from_path(&relmap_dir, control.endianness())?;
+    let version_directory =
+        tablespace_version_directory(reader.major_version(), control.catalog_version_no());
+    let path = relation_path(data_directory, database, class, &version_directory, &relmap)?;
+
+    let dropped = relation
+        .columns()
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| column.attisdropped())
+        .map(|(index, _)| index)
+        .collect();
+    let toast = catalog
+        .relation_by_oid(class.reltoastrelid())
+        .map(|toast| (toast.oid(), toast.class().relfilenode()));
+    Ok(NamedTable {
+        path,
+        descriptor: catalog.tuple_descriptor(relation),
+        dropped,
+        toast,
+    })
+}
+
+/// Adds the relations with storage in `database` to `relations`, shared ones
+/// included, by where they are stored. Storage that only uncommitted `pg_class`
+/// rows point to is added as such.
+fn database_relations(
+    data_directory: &Path,
+    database: &DatabaseRow,
+    control: &ControlFileData,
+    global_relmap: &RelMapFile,
+    relations: &mut HashMap<RelFileLocator, StoredRelation>,
+) -> anyhow::Result<()> {
+    let mut reader = CatalogReader::open(data_directory, database, control)?;
+    let catalog = reader.read()?;
+    let relmap = RelMapFile::from_path(reader.database_dir(), control.endianness())?;
+    let committed = catalog
+        .schemas()
+        .iter()
+        .flat_map(|schema| schema.relations().iter().map(Relation::class));
+    let uncommitted = reader.uncommitted_classes()?;
+    for (class, uncommitted) in committed
+        .map(|class| (class, false))
+        .chain(uncommitted.iter().map(|class| (class, true)))
+    {
+        if !class.relkind().has_storage() {
+            continue;
+        }
+        let relmap = if class.relisshared() {
+            global_relmap
+        } else {
+            &relmap
+        };
+        // The schema of an uncommitted relation may be uncommitted too.
+        let schema = catalog
+            .schemas()
+            .iter()
+            .find(|schema| schema.oid() == class.relnamespace())
+            .map_or_else(
+                || class.relnamespace().to_string(),
+                |schema| schema.name().to_string(),
+            );
+        relations
+            .entry(relation_locator(database, class, relmap)?)
+            .or_insert(StoredRelation::new(
+                class.relkind(),
+                format!("{}.{}", schema, class.relname()),
+                uncommitted,
+            ));
+    }
+    Ok(())
+}
+
+/// Reads `pg_control` from the data directory enclosing `path`, if there is one.
+///
+/// A control file failing its CRC check is ignored with a warning, since none of
+/// its settings can be trusted.
+fn enclosing_control_file(path: &Path) -> anyhow::Result<Option<ControlFileData>> {
+    let Some(data_directory) = find_data_directory(path) else {
+        return Ok(None);
+    };
+    let control = ControlFileData::from_data_directory(&data_directory)?;
+    if !control.crc_is_valid() {
+        eprintln!(
+            "warning: ignoring {}: calculated CRC checksum does not match value stored in file",
+            data_directory.join("global").join("pg_control").display()
+        );
+        return Ok(None);
+    }
+    Ok(Some(control))
+}
+
+/// Byte order of the cluster's files: that of pg_control, else this machine's.
+fn cluster_endianness(control: Option<&ControlFileData>) -> Endianness {
+    control.map_or_else(get_system_endianness, ControlFileData::endianness)
+}
+
+/// Formats a `pg_control` time, in seconds since the Unix epoch.
+fn control_time(secs: i64) -> String {
+    const POSTGRES_EPOCH_SECS: i64 = 946_684_800;
+    Datum::TimestampTz((secs - POSTGRES_EPOCH_SECS) * 1_000_000).to_string()
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// Prints the fields of `pg_control` with `pg_controldata`'s labels.
+fn print_control_file(control: &ControlFileData) {
+    let checkpoint = control.check_point_copy();
+    let by_value = |by_val: bool| if by_val { "by value" } else { "by reference" };
+    let nonce: String = control
+        .mock_authentication_nonce()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    let mut fields = vec![
+        (
+            "pg_control version number",
+            control.pg_control_version().to_string(),
+        ),
+        (
+            "Catalog version number",
+            control.catalog_version_no().to_string(),
+        ),
+        (
+            "Database system identifier",
+            control.system_identifier().to_string(),
+        ),
+        ("Database cluster state", control.state().to_string()),
+        ("pg_control last modified", control_time(control.time())),
+        (
+            "Latest checkpoint location",
+            control.check_point().to_string(),
+        ),
+        (
+            "Latest checkpoint's REDO location",
+            checkpoint.redo().to_string(),
+        ),
+        (
+            "Latest checkpoint's REDO WAL file",
+            control.wal_file_name(checkpoint.this_timeline_id(), checkpoint.redo()),
+        ),
+        (
+            "Latest checkpoint's TimeLineID",
+            checkpoint.this_timeline_id().to_string(),
+        ),
+        (
+            "Latest checkpoint's PrevTimeLineID",
+            checkpoint.prev_timeline_id().to_string(),
+        ),
+        (
+            "Latest checkpoint's full_page_writes",
+            on_off(checkpoint.full_page_writes()).to_string(),
+        ),
+        (
+            "Latest checkpoint's NextXID",
+            format!("{}:{}", checkpoint.next_xid_epoch(), checkpoint.next_xid()),
+        ),
+        (
+            "Latest checkpoint's NextOID",
+            checkpoint.next_oid().to_string(),
+        ),
+        (
+            "Latest checkpoint's NextMultiXactId",
+            checkpoint.next_multi().to_string(),
+        ),
+        (
+            "Latest checkpoint's NextMultiOffset",
+            checkpoint.next_multi_offset().to_string(),
+        ),
+        (
+            "Latest checkpoint's oldestXID",
+            checkpoint.oldest_xid().to_string(),
+        ),
+        (
+            "Latest checkpoint's oldestXID's DB",
+            checkpoint.oldest_xid_db().to_string(),
+        ),
+        (
+            "Latest checkpoint's oldestActiveXID",
+            checkpoint.oldest_active_xid().to_string(),
+        ),
+        (
+            "Latest checkpoint's oldestMultiXid",
+            checkpoint.oldest_multi().to_string(),
+        ),
+        (
+            "Latest checkpoint's oldestMulti's DB",
+            checkpoint.oldest_multi_db().to_string(),
+        ),
+        (
+            "Latest checkpoint's oldestCommitTsXid",
+            checkpoint.oldest_commit_ts_xid().to_string(),
+        ),
+        (
+            "Latest checkpoint's newestCommitTsXid",
+            checkpoint.newest_commit_ts_xid().to_string(),
+        ),
+        ("Time of latest checkpoint", control_time(checkpoint.time())),
+        (
+            "Fake LSN counter for unlogged rels",
+            control.unlogged_lsn().to_string(),
+        ),
+        (
+            "Minimum recovery ending location",
+            control.min_recovery_point().to_string(),
+        ),
+        (
+            "Min recovery ending loc's timeline",
+            control.min_recovery_point_tli().to_string(),
+        ),
+        (
+            "Backup start location",
+            control.backup_start_point().to_string(),
+        ),
+        (
+            "Backup end location",
+            control.backup_end_point().to_string(),
+        ),
+        (
+            "End-of-backup record required",
+            if control.backup_end_required() {
+                "yes"
+            } else {
+                "no"
+            }
+            .to_string(),
+        ),
+        ("wal_level setting", control.wal_level().to_string()),
+        (
+            "wal_log_hints setting",
+            on_off(control.wal_log_hints()).to_string(),
+        ),
+        (
+            "max_connections setting",
+            control.max_connections().to_string(),
+        ),
+        (
+            "max_worker_processes setting",
+            control.max_worker_processes().to_string(),
+        ),
+    ];
+    if let Some(max_wal_senders) = control.max_wal_senders() {
+        fields.push(("max_wal_senders setting", max_wal_senders.to_string()));
+    }
+    fields.extend([
+        (
+            "max_prepared_xacts setting",
+            control.max_prepared_xacts().to_string(),
+        ),
+        (
+            "max_locks_per_xact setting",
+            control.max_locks_per_xact().to_string(),
+        ),
+        (
+            "track_commit_timestamp setting",
+            on_off(control.track_commit_timestamp()).to_string(),
+        ),
+        ("Maximum data alignment", control.max_align().to_string()),
+        ("Database block size", control.blcksz().to_string()),
+        (
+            "Blocks per segment of large relation",
+            control.relseg_size().to_string(),
+        ),
+        ("WAL block size", control.xlog_blcksz().to_string()),
+        ("Bytes per WAL segment", control.xlog_seg_size().to_string()),
+        (
+            "Maximum length of identifiers",
+            control.name_data_len().to_string(),
+        ),
+        (
+            "Maximum columns in an index",
+            control.index_max_keys().to_string(),
+        ),
+        (
+            "Maximum size of a TOAST chunk",
+            control.toast_max_chunk_size().to_string(),
+        ),
+        (
+            "Size of a large-object chunk",
+            control.loblksize().to_string(),
+        ),
+        // Floating-point timestamps were removed in PostgreSQL 10.
+        ("Date/time type storage", "64-bit integers".to_string()),
+    ]);
+    if let Some(float4_by_val) = control.float4_by_val() {
+        fields.push((
+            "Float4 argument passing",
+            by_value(float4_by_val).to_string(),
+        ));
+    }
+    fields.push((
+        "Float8 argument passing",
+        by_value(control.float8_by_val()).to_string(),
+    ));
+    fields.push((
+        "Data page checksum version",
+        control.data_checksum_version().to_string(),
+    ));
+    if let Some(signed) = control.default_char_signedness() {
+        fields.push((
+            "Default char data signedness",
+            if signed { "signed" } else { "unsigned" }.to_string(),
+        ));
+    }
+    fields.push(("Mock authentication nonce", nonce));
+
+    for (label, value) in fields {
+        // pg_controldata pads labels to 38 columns, letting longer ones touch the value.
+        println!("{:<38}{}", format!("{}:", label), value);
+    }
+}
+
+/// Picks the block size for a relation file: the override, then pg_control.
+///
+/// `None` leaves it to `RelationReader` to take the size from the file itself.
+fn configured_block_size(
+    block_size: Option<usize>,
+    control: Option<&ControlFileData>,
+) -> Option<usize> {
+    block_size.or_else(|| control.map(|control| control.blcksz() as usize))
+}
+
+fn check_file(
+    path: &Path,
+    segment: u32,
+    block_size: Option<usize>,
+    control: Option<&ControlFileData>,
+    endianness: Endianness,
+    totals: &mut ChecksumTotals,
+) -> anyhow::Result<()> {
+    let file = File::open(path)?;
+    let block_size = configured_block_size(block_size, control);
+    let mut relation = RelationReader::new(file, block_size, endianness)?;
+    let block_size = relation.block_size();
+    let segment_blocks = control.map_or_else(|| relseg_size(block_size), |c| c.relseg_size());
+    let mut buffer = vec![0u8; block_size];
+    // A stray file such as `16384.40000` names blocks past the end of a relation.
+    let out_of_range = || {
+        anyhow::anyhow!(
+            "{}: segment {} lies past the last block a relation can have",
+            path.display(),
+            segment
+        )
+    };
+    let first_block = segment
+        .checked_mul(segment_blocks)
+        .ok_or_else(out_of_range)?;
+
+    for block_in_segment in 0..relation.n_blocks() {
+        relation.read_block_bytes(BlockNumber(block_in_segment), &mut buffer)?;
+
+        let block_number = BlockNumber(
+            first_block
+                .checked_add(block_in_segment)
+                .ok_or_else(out_of_range)?,
+        );
+        let status = match control {
+            Some(control) => verify_cluster_page_checksum(
+                &buffer,
+                block_number,
+                endianness,
+                control.data_checksum_version(),
+            ),
+            None => verify_page_checksum(&buffer, block_number, endianness),
+        };
+        match status {
+            ChecksumStatus::Valid => {}
+            ChecksumStatus::Disabled => totals.disabled += 1,
+            ChecksumStatus::Invalid { stored, computed } => {
+                totals.bad += 1;
+                println!(
+                    "checksum verification failed in file \"{}\", block {}: calculated checksum {:X} but block contains {:X}",
+                    path.display(),
+                    *block_number,
+                    computed,
+                    stored
+                );
+            }
+        }
+        totals.blocks += 1;
+    }
+
+    if relation.trailing_bytes() > 0 {
+        anyhow::bail!(
+            "could not read block {} in file \"{}\": read {} of {}",
+            relation.n_blocks(),
+            path.display(),
+            relation.trailing_bytes(),
+            block_size
+        );
+    }
+
+    totals.files += 1;
+    Ok(())
+}
+
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
     match cli.command {
-        Commands::Table { filename } => {
-            let mut file = File::open(filename)?;
-            let endianness = get_system_endianness();
-            let header = read_all_pages(&mut file, endianness)?;
-            println!("{:#?}", header);
+        Commands::Table {
+            filename,
+            datadir,
+            db,
+            table,
+            block_size,
+            start_block,
+            end_block,
+            types,
+            compression,
+            no_toast,
+            snapshot,
+            aborted,
+            all,
+            locks,
+        } => {
+            // A table in another tablespace is reached through a symlink, so its
+            // data directory cannot be found from the path.
+            let data_directory = match (&datadir, &filename) {
+                (Some(datadir), _) => Some(PathBuf::from(datadir)),
+                (None, Some(filename)) => find_data_directory(Path::new(filename)),
+                (None, None) => None,
+            };
+            let control = match &data_directory {
+                Some(data_directory) => enclosing_control_file(data_directory)?,
+                None => None,
+            };
+            let named = match (&data_directory, &db, &table) {
+                (Some(data_directory), Some(db), Some(table)) => {
+                    let control = control
+                        .as_ref()
+                        .ok_or_else(|| anyhow::anyhow!("--table needs a usable pg_control"))?;
+                    Some(find_table(data_directory, control, db, table)?)
+                }
+                _ => None,
+            };
+            let (filename, descriptor, dropped, toast_relation) = match (named, filename) {
+                (Some(named), _) => {
+                    eprintln!("reading {}", named.path.display());
+                    (
+                        named.path,
+                        Some(named.descriptor),
+                        named.dropped,
+                        named.toast,
+                    )
+                }
+                (None, Some(filename)) => (
+                    PathBuf::from(filename),
+                    types.as_deref().map(tuple_descriptor).transpose()?,
+                    Vec::new(),
+                    None,
+                ),
+                (None, None) => anyhow::bail!("either --filename or --table is required"),
+            };
+            let endianness = cluster_endianness(control.as_ref());
+            let block_size = configured_block_size(block_size, control.as_ref());
+            let relseg_size = control.as_ref().map(|control| control.relseg_size());
+            let mut relation =
+                RelationReader::open_segments(&filename, block_size, relseg_size, endianness)?;
+            let toast = match filename.parent() {
+                Some(database_dir) if !no_toast => {
+                    let mut toast = ToastReader::new(
+                        database_dir,
+                        Some(relation.block_size()),
+                        relseg_size,
+                        endianness,
+                    );
+                    if let Some((toast_relid, relfilenode)) = toast_relation {
+                        toast.set_filenode(toast_relid, relfilenode);
+                    }
+                    if let (Some(data_directory), Some(control)) = (&data_directory, &control) {
+                        toast.set_oracle(Box::new(ClusterOracle::open(data_directory, control)));
+                    }
+                    Some(toast)
+                }
+                _ => None,
+            };
+            let visibility = snapshot.map(|snapshot| {
+                let oracle: Box<dyn TransactionOracle> = match (&data_directory, &control) {
+                    (Some(data_directory), Some(control)) if aborted.is_empty() => {
+                        Box::new(ClusterOracle::open(data_directory, control))
+                    }
+                    _ => {
+                        let aborted: HashSet<TransactionId> =
+                            aborted.into_iter().map(TransactionId).collect();
+                        let running = snapshot.clone();
+                        Box::new(move |xid: TransactionId| {
+                            Ok(if aborted.contains(&xid) {
+                                TransactionStatus::Aborted
+                            } else if running.is_running(xid) {
+                                TransactionStatus::InProgress
+                            } else {
+                                TransactionStatus::Committed
+                            })
+                        })
+                    }
+                };
+                (snapshot, oracle)
+            });
+            let locks = match (locks, &data_directory, &control) {
+                (false, _, _) => None,
+                (true, Some(data_directory), Some(control)) => {
+                    let mut multixact =
+                        MultiXactReader::open(data_directory, relation.block_size(), endianness);
+                    let checkpoint = control.check_point_copy();
+                    multixact.set_next(checkpoint.next_multi(), checkpoint.next_multi_offset());
+                    Some(multixact)
+                }
+                (true, _, _) => anyhow::bail!("--locks needs a relation inside a data directory"),
+            };
+            let mut printer = descriptor.map(|descriptor| RowPrinter {
+                descriptor,
+                compression,
+                toast,
+                visibility,
+                all,
+                locks,
+                dropped,
+                endianness,
+            });
+            let end_block = end_block.unwrap_or(relation.n_blocks());
+            let pages = if start_block == 0 && end_block >= relation.n_blocks() {
+                relation.pages()
+            } else {
+                relation.range(BlockNumber(start_block)..BlockNumber(end_block))
+            };
+            for page in pages {
+                let page = page?;
+                match &mut printer {
+                    Some(printer) => printer.print_page(&page)?,
+                    None => println!("{:#?}", page),
+                }
+            }
+        }
+        Commands::Checksums { path, block_size } => {
+            let path = Path::new(&path);
+            let mut files = Vec::new();
+            if path.is_dir() {
+                collect_relation_files(path, &mut files)?;
+            } else {
+                let segment = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(relation_segment_number)
+                    .unwrap_or(0);
+                files.push((path.to_path_buf(), segment));
+            }
+
+            let control = enclosing_control_file(path)?;
+            let endianness = cluster_endianness(control.as_ref());
+            let mut totals = ChecksumTotals::default();
+            for (file, segment) in &files {
+                check_file(
+                    file,
+                    *segment,
+                    block_size,
+                    control.as_ref(),
+                    endianness,
+                    &mut totals,
+                )?;
+            }
+
+            eprintln!("Files scanned:   {}", totals.files);
+            eprintln!("Blocks scanned:  {}", totals.blocks);
+            eprintln!("Bad checksums:   {}", totals.bad);
+            eprintln!("No checksum:     {}", totals.disabled);
+            if totals.bad > 0 {
+                anyhow::bail!("{} blocks failed checksum verification", totals.bad);
+            }
+        }
+        Commands::Xact {
+            xids,
+            datadir,
+            block_size,
+        } => {
+            let datadir = Path::new(&datadir);
+            let control = enclosing_control_file(datadir)?;
+            let endianness = cluster_endianness(control.as_ref());
+            let block_size = match (block_size, &control) {
+                (Some(block_size), _) => block_size,
+                (None, Some(control)) => control.blcksz() as usize,
+                (None, None) => anyhow::bail!(
+                    "no usable pg_control in {}; pass --block_size",
+                    datadir.display()
+                ),
+            };
+            let clog = ClogReader::open(datadir, block_size);
+            let subtrans = SubtransReader::open(datadir, block_size, endianness);
+            for xid in xids {
+                let xid = TransactionId(xid);
+                let parents = match subtrans.ancestors(xid) {
+                    // Segments older than the oldest running transaction are removed.
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                    result => result?,
+                };
+                let parents: Vec<String> = parents.iter().map(ToString::to_string).collect();
+                let parents = if parents.is_empty() {
+                    "-".to_string()
+                } else {
+                    parents.join(",")
+                };
+                println!("{}\t{}\t{}", xid, clog.status(xid)?, parents);
+            }
+        }
+        Commands::Control { datadir } => {
+            let control = ControlFileData::from_data_directory(Path::new(&datadir))?;
+            if !control.crc_is_valid() {
+                eprintln!(
+                    "WARNING: Calculated CRC checksum does not match value stored in file.\n\
+                     Either the file is corrupt, or it has a different layout than this program\n\
+                     is expecting.  The results below are untrustworthy."
+                );
+            }
+            print_control_file(&control);
+        }
+        Commands::Relmap { path } => {
+            let path = Path::new(&path);
+            let control = enclosing_control_file(path)?;
+            let relmap = RelMapFile::from_path(path, cluster_endianness(control.as_ref()))?;
+            if !relmap.crc_is_valid() {
+                eprintln!(
+                    "warning: relation map file contains incorrect checksum: calculated {:X} but file contains {:X}",
+                    relmap.computed_crc(),
+                    relmap.crc()
+                );
+            }
+            for mapping in relmap.mappings() {
+                println!("{}\t{}", mapping.mapoid(), mapping.mapfilenumber());
+            }
+        }
+        Commands::Inventory { datadir } => {
+            let data_directory = Path::new(&datadir);
+            let control = enclosing_control_file(data_directory)?
+                .ok_or_else(|| anyhow::anyhow!("inventory needs a usable pg_control"))?;
+            let shared = CatalogReader::open_shared(data_directory, &control)?;
+            let global_relmap =
+                RelMapFile::from_path(&data_directory.join("global"), control.endianness())?;
+
+            let mut relations = HashMap::new();
+            let mut unreadable = HashSet::new();
+            for database in shared.databases()? {
+                if let Err(err) = database_relations(
+                    data_directory,
+                    &database,
+                    &control,
+                    &global_relmap,
+                    &mut relations,
+                ) {
+                    eprintln!(
+                        "warning: cannot read the catalogs of database \"{}\": {}",
+                        database.datname(),
+                        err
+                    );
+                    unreadable.insert(database.oid());
+                }
+            }
+
+            let version_directory =
+                tablespace_version_directory(shared.major_version(), control.catalog_version_no());
+            let (mut total_files, mut total_size) = (0u64, 0u64);
+            let (mut orphan_files, mut orphan_size) = (0u64, 0u64);
+            let (mut uncommitted_files, mut uncommitted_size) = (0u64, 0u64);
+            for (file, owner) in
+                inventory(data_directory, &version_directory, &relations, &unreadable)?
+            {
+                let name = file.name();
+                let (kind, relation) = match owner {
+                    FileOwner::Relation(relation) if relation.is_uncommitted() => {
+                        uncommitted_files += 1;
+                        uncommitted_size += file.size();
+                        ("uncommitted", relation.name())
+                    }
+                    FileOwner::Relation(relation) => (relation.kind().name(), relation.name()),
+                    FileOwner::Unknown => ("?", "?"),
+                    FileOwner::Orphan => {
+                        orphan_files += 1;
+                        orphan_size += file.size();
+                        ("orphan", "-")
+                    }
+                };
+                total_files += 1;
+                total_size += file.size();
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    file.path().display(),
+                    file.size(),
+                    name.fork(),
+                    name.segment(),
+                    name.backend()
+                        .map_or_else(|| "-".to_string(), |backend| backend.to_string()),
+                    kind,
+                    relation
+                );
+            }
+            eprintln!("Files:         {} ({} bytes)", total_files, total_size);
+            eprintln!("Orphan files:  {} ({} bytes)", orphan_files, orphan_size);
+            eprintln!(
+                "Uncommitted:   {} ({} bytes)",
+                uncommitted_files, uncommitted_size
+            );
+        }
+        Commands::Fsm {
+            filename,
+            block_size,
+            check,
+        } => {
+            let heap_path = PathBuf::from(filename.strip_suffix("_fsm").unwrap_or(&filename));
+            let fsm_path = PathBuf::from(format!("{}_fsm", heap_path.display()));
+            if !fsm_path.is_file() {
+                // The server creates the map lazily, on the first vacuum or once
+                // inserts fill a page.
+                anyhow::bail!("{} has no free space map yet", heap_path.display());
+            }
+            let control = enclosing_control_file(&heap_path)?;
+            let endianness = cluster_endianness(control.as_ref());
+            let block_size = configured_block_size(block_size, control.as_ref());
+            let relseg_size = control.as_ref().map(|control| control.relseg_size());
+            let mut heap =
+                RelationReader::open_segments(&heap_path, block_size, relseg_size, endianness)?;
+            let mut fsm = FsmReader::new(RelationReader::open_segments(
+                &fsm_path,
+                Some(heap.block_size()),
+                relseg_size,
+                endianness,
+            )?);
+
+            if !check {
+                for block in (0..heap.n_blocks()).map(BlockNumber) {
+                    let category = fsm.category(block)?;
+                    println!(
+                        "{}\t{}\t{}",
+                        *block,
+                        category,
+                        fsm.layout().category_to_avail(category)
+                    );
+                }
+                return Ok(());
+            }
+
+            let blocks = compare_free_space(&mut heap, &mut fsm)?;
+            let mut inconsistent_nodes = 0;
+            for block in (0..fsm.n_blocks()).map(BlockNumber) {
+                if let Some(page) = fsm.page(block)? {
+                    inconsistent_nodes += page.inconsistent_nodes().len();
+                }
+            }
+            for block in &blocks {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    *block.block(),
+                    block.category(),
+                    block.recorded(),
+                    block.actual(),
+                    block.status()
+                );
+            }
+            let count = |status| {
+                blocks
+                    .iter()
+                    .filter(|block| block.status() == status)
+                    .count()
+            };
+            eprintln!("Blocks checked:      {}", blocks.len());
+            eprintln!(
+                "Overstated:          {}",
+                count(FreeSpaceStatus::Overstated)
+            );
+            eprintln!(
+                "Understated:         {}",
+                count(FreeSpaceStatus::Understated)
+            );
+            eprintln!("Inconsistent nodes:  {}", inconsistent_nodes);
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pg_peek_lib::crc::crc32c;
+    use pg_peek_lib::datum::oid;
+    use pg_peek_lib::testing::{tuple, write_relation, Field};
+    use std::fs;
+
+    /// Writes an empty `pg_filenode.map` of PostgreSQL 15 into `directory`.
+    fn write_relmap(directory: &Path) {
+        let mut bytes = vec![0u8; 512];
+        bytes[0..4].copy_from_slice(&0x0059_2717u32.to_le_bytes());
+        let crc = crc32c(&bytes[..8 + 62 * 8]);
+        bytes[504..508].copy_from_slice(&crc.to_le_bytes());
+        fs::write(directory.join("pg_filenode.map"), bytes).unwrap();
+    }
+
+    /// The fields of a PostgreSQL 15 `pg_control` the catalog readers use.
+    fn control() -> ControlFileData {
+        let mut bytes = vec![0u8; 8192];
+        bytes[8..12].copy_from_slice(&1300u32.to_le_bytes());
+        bytes[12..16].copy_from_slice(&202209061u32.to_le_bytes());
+        bytes[64..72].copy_from_slice(&1000u64.to_le_bytes());
+        bytes[216..220].copy_from_slice(&8192u32.to_le_bytes());
+        bytes[220..224].copy_from_slice(&131072u32.to_le_bytes());
+        bytes[232..236].copy_from_slice(&64u32.to_le_bytes());
+        ControlFileData::from_bytes(&bytes, Endianness::LittleEndian).unwrap()
+    }
+
+    fn class(oid: u32, relname: &str, relnamespace: u32, relkind: u8, toast: u32) -> Vec<u8> {
+        let mut fields = vec![Field::Oid(oid), Field::Name(relname)];
+        // relnamespace to reltablespace
+        fields.extend([relnamespace, 0, 0, 10, 2, oid + 1, 0].map(Field::Oid));
+        fields.extend([Field::Int4(1), Field::Int4(0), Field::Int4(0)]);
+        fields.extend([
+            Field::Oid(toast),
+            Field::Bool(false),
+            Field::Bool(false),
+            Field::Char(b'p'),
+            Field::Char(relkind),
+            Field::Int2(3),
+        ]);
+        tuple(&fields)
+    }
+
+    fn attribute(attnum: i16, attname: &str, atttypid: u32, attlen: i16, attalign: u8) -> Vec<u8> {
+        let mut fields = vec![
+            Field::Oid(16500),
+            Field::Name(attname),
+            Field::Oid(atttypid),
+            Field::Int4(-1),
+            Field::Int2(attlen),
+            Field::Int2(attnum),
+            Field::Int4(0),
+            Field::Int4(-1),
+            Field::Int4(-1),
+            Field::Bool(attlen > 0),
+            Field::Char(attalign),
+            Field::Char(if attlen > 0 { b'p' } else { b'x' }),
+            Field::Char(0),
+        ];
+        // attnotnull to attislocal, with attisdropped set for a dropped column
+        fields.extend([false, false, false].map(Field::Bool));
+        fields.extend([Field::Char(0), Field::Char(0)]);
+        fields.extend([Field::Bool(atttypid == 0), Field::Bool(true)]);
+        fields.extend([Field::Int4(0), Field::Oid(0)]);
+        tuple(&fields)
+    }
+
+    #[test]
+    fn finds_tables_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path();
+        fs::write(data.join("PG_VERSION"), "15\n").unwrap();
+        let global = data.join("global");
+        fs::create_dir(&global).unwrap();
+        write_relmap(&global);
+        let database = |oid: u32, datname: &str, dattablespace: u32| {
+            let mut fields = vec![Field::Oid(oid), Field::Name(datname), Field::Oid(10)];
+            fields.extend([Field::Int4(6), Field::Char(b'c'), Field::Bool(false)]);
+            fields.extend([Field::Bool(true), Field::Int4(-1)]);
+            fields.extend([722, 1, dattablespace].map(Field::Oid));
+            tuple(&fields)
+        };
+        write_relation(
+            &global.join("1262"),
+            &[
+                database(5, "postgres", 1663),
+                database(16384, "shop", 16390),
+            ],
+        );
+
+        // The database lives in a tablespace, catalogs and all.
+        let database_dir = data.join("pg_tblspc/16390/PG_15_202209061/16384");
+        fs::create_dir_all(&database_dir).unwrap();
+        fs::write(database_dir.join("PG_VERSION"), "15\n").unwrap();
+        write_relmap(&database_dir);
+        write_relation(
+            &database_dir.join("1259"),
+            &[
+                class(16500, "orders", 2200, b'r', 16503),
+                class(16503, "pg_toast_16500", 99, b't', 0),
+            ],
+        );
+        write_relation(
+            &database_dir.join("1249"),
+            &[
+                attribute(1, "id", oid::INT4OID, 4, b'i'),
+                attribute(2, "........pg.dropped.2........", 0, 8, b'd'),
+                attribute(3, "note", oid::TEXTOID, -1, b'i'),
+            ],
+        );
+        write_relation(&database_dir.join("1247"), &[]);
+        let namespace = |oid: u32, nspname: &str| {
+            tuple(&[Field::Oid(oid), Field::Name(nspname), Field::Oid(10)])
+        };
+        write_relation(
+            &database_dir.join("2615"),
+            &[namespace(2200, "public"), namespace(99, "pg_toast")],
+        );
+
+        let control = control();
+        for (db, table) in [("shop", "orders"), ("16384", "public.orders")] {
+            let found = find_table(data, &control, db, table).unwrap();
+            assert_eq!(found.path, database_dir.join("16501"));
+            let types: Vec<u32> = found
+                .descriptor
+                .attributes()
+                .iter()
+                .map(Attribute::type_oid)
+                .collect();
+            assert_eq!(types, [oid::INT4OID, 0, oid::TEXTOID]);
+            assert_eq!(found.dropped, [1]);
+            assert_eq!(found.toast, Some((16503, 16504)));
+        }
+        assert!(find_table(data, &control, "nope", "orders").is_err());
+        assert!(find_table(data, &control, "shop", "app.orders").is_err());
+    }
+}